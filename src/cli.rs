@@ -0,0 +1,130 @@
+//! Command line arguments of the native binary
+
+use std::time::Duration;
+
+use tracing::warn;
+
+use rsk_simulation::constants::{DEFAULT_DECISION_RATE, DEFAULT_PUBLISH_RATE};
+use rsk_simulation::game_state::Team;
+
+pub struct Args {
+    /// Rate at which the game state is sent to the clients (in Hz)
+    pub publish_rate: usize,
+    /// Add velocities, frame and timestamp to the game state
+    pub extended_state: bool,
+    /// Run without GUI
+    pub headless: bool,
+    /// Number of DT steps between two frames in lockstep mode. None if lockstep is disabled
+    pub lockstep: Option<usize>,
+    /// Maximum time to wait for the controllers in lockstep mode
+    pub lockstep_timeout: Duration,
+    /// Names of the built-in strategies [blue, green]
+    pub bots: [Option<String>; 2],
+    /// Rate at which the built-in strategies are updated (in Hz)
+    pub decision_rate: usize,
+    /// File in which the match is recorded
+    pub record: Option<String>,
+    /// Recording to play instead of running the simulation
+    pub replay: Option<String>,
+    /// Recording to re-simulate
+    pub resimulate: Option<String>,
+    /// Json file with the physical parameters, the missing ones keep their default value
+    pub physics: Option<String>,
+    /// (recording, directory) : converts a recording to csv files
+    pub export: Option<(String, String)>,
+    /// (recording, output) : renders a recording to an animated svg (output ending with .svg) or to a directory of svg frames
+    pub render: Option<(String, String)>,
+    /// Frames per second of match time for `render`
+    pub render_fps: f64,
+    /// Scenario files, run one after the other in headless mode
+    pub scenarios: Vec<String>,
+    /// Starts with a penalty shootout, the team kicks first
+    pub shootout: Option<Team>,
+}
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            publish_rate: DEFAULT_PUBLISH_RATE,
+            extended_state: false,
+            headless: false,
+            lockstep: None,
+            lockstep_timeout: Duration::from_secs(1),
+            bots: [None, None],
+            decision_rate: DEFAULT_DECISION_RATE,
+            record: None,
+            replay: None,
+            resimulate: None,
+            physics: None,
+            export: None,
+            render: None,
+            render_fps: 10.,
+            scenarios: Vec::new(),
+            shootout: None,
+        }
+    }
+}
+impl Args {
+    pub fn parse() -> Self {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--publish-rate" => {
+                    args.publish_rate = iter.next()
+                        .and_then(|r| r.parse().ok())
+                        .expect("--publish-rate expects a rate in Hz");
+                },
+                "--extended-state" => args.extended_state = true,
+                "--headless" => args.headless = true,
+                "--lockstep" => {
+                    args.lockstep = Some(iter.next()
+                        .and_then(|s| s.parse().ok())
+                        .expect("--lockstep expects a number of steps"));
+                },
+                "--lockstep-timeout" => {
+                    args.lockstep_timeout = iter.next()
+                        .and_then(|t| t.parse().ok())
+                        .map(Duration::from_millis)
+                        .expect("--lockstep-timeout expects a duration in ms");
+                },
+                "--blue-bot" => args.bots[0] = Some(iter.next().expect("--blue-bot expects a strategy name")),
+                "--green-bot" => args.bots[1] = Some(iter.next().expect("--green-bot expects a strategy name")),
+                "--decision-rate" => {
+                    args.decision_rate = iter.next()
+                        .and_then(|r| r.parse().ok())
+                        .expect("--decision-rate expects a rate in Hz");
+                },
+                "--record" => args.record = Some(iter.next().expect("--record expects a file")),
+                "--replay" => args.replay = Some(iter.next().expect("--replay expects a file")),
+                "--resimulate" => args.resimulate = Some(iter.next().expect("--resimulate expects a file")),
+                "--physics" => args.physics = Some(iter.next().expect("--physics expects a json file")),
+                "--export" => {
+                    let recording = iter.next().expect("--export expects a recording and a directory");
+                    let dir = iter.next().expect("--export expects a recording and a directory");
+                    args.export = Some((recording, dir));
+                },
+                "--render" => {
+                    let recording = iter.next().expect("--render expects a recording and an output");
+                    let output = iter.next().expect("--render expects a recording and an output");
+                    args.render = Some((recording, output));
+                },
+                "--render-fps" => {
+                    args.render_fps = iter.next()
+                        .and_then(|r| r.parse().ok())
+                        .filter(|r: &f64| *r > 0.)
+                        .expect("--render-fps expects a positive number of frames per second");
+                },
+                "--scenario" => args.scenarios.push(iter.next().expect("--scenario expects a json file")),
+                "--shootout" => {
+                    args.shootout = Some(match iter.next().as_deref() {
+                        Some("blue") => Team::Blue,
+                        Some("green") => Team::Green,
+                        _ => panic!("--shootout expects the team that kicks first : blue or green")
+                    });
+                },
+                _ => warn!("Unknown argument : {}", arg)
+            }
+        }
+        args
+    }
+}
@@ -0,0 +1,247 @@
+//! A client for the control api of the simulation (and of the official game controller).
+//! Mirrors the python `rsk.Client` :
+//! ```ignore
+//! let mut client = Client::new("127.0.0.1", "")?;
+//! client.on_update(|client, dt| {
+//!     if let Some(ball) = client.ball() {
+//!         client.blue1().goto((ball.x, ball.y, 0.), false).unwrap();
+//!     }
+//! })?;
+//! ```
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use nalgebra::Point2;
+use serde_json::{json, Value};
+use tokio::{runtime::Runtime, sync::{watch, Mutex}};
+use tracing::warn;
+use zeromq::{ReqSocket, Socket, SocketRecv, SocketSend, SubSocket, ZmqError};
+
+use crate::debug::Primitive;
+use crate::game_state::{GameState, Geometry, Pose, Robot, Team};
+
+#[derive(Debug)]
+pub enum ClientError {
+    Zmq(ZmqError),
+    Json(serde_json::Error),
+    /// The game controller refused the command (message)
+    Refused(String),
+    /// No game state received before the timeout, the simulation or the game controller is probably not running
+    Timeout,
+    /// The game state socket stopped
+    Closed,
+}
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Zmq(e) => write!(f, "zmq error : {}", e),
+            ClientError::Json(e) => write!(f, "json error : {}", e),
+            ClientError::Refused(msg) => write!(f, "command refused : {}", msg),
+            ClientError::Timeout => write!(f, "no game state received"),
+            ClientError::Closed => write!(f, "game state socket closed")
+        }
+    }
+}
+impl std::error::Error for ClientError {}
+impl From<ZmqError> for ClientError {
+    fn from(e: ZmqError) -> Self {
+        ClientError::Zmq(e)
+    }
+}
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::Json(e)
+    }
+}
+
+/// Maximum time `Client::new` waits for the first game state
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Client {
+    key: String,
+    rt: Runtime,
+    ctrl_socket: Mutex<ReqSocket>,
+    /// Last game state received
+    gs: watch::Receiver<GameState>,
+}
+impl Client {
+    /// Connects to the game controller at `host` (for example "127.0.0.1") and waits for the first game state
+    pub fn new(host: &str, key: &str) -> Result<Self, ClientError> {
+        Self::with_timeout(host, key, CONNECT_TIMEOUT)
+    }
+    /// Like `new`, waits at most `timeout` for the first game state
+    pub fn with_timeout(host: &str, key: &str, timeout: Duration) -> Result<Self, ClientError> {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut state_socket = SubSocket::new();
+        let mut ctrl_socket = ReqSocket::new();
+
+        rt.block_on(state_socket.connect(&format!("tcp://{}:7557", host)))?;
+        rt.block_on(state_socket.subscribe(""))?;
+        rt.block_on(ctrl_socket.connect(&format!("tcp://{}:7558", host)))?;
+
+        let (sender, mut gs) = watch::channel(GameState::default());
+        rt.spawn(async move {
+            loop {
+                let msg = match state_socket.recv().await {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        warn!("Error when receiving game state : {}", e);
+                        break
+                    }
+                };
+                let json = match msg.get(0) {
+                    Some(json) => json,
+                    None => {
+                        warn!("Received empty message");
+                        continue
+                    }
+                };
+                match serde_json::from_slice(json) {
+                    Ok(new_gs) => if sender.send(new_gs).is_err() {
+                        // The client has been dropped
+                        break
+                    },
+                    Err(e) => warn!("Error when deserializing game state : {}", e)
+                }
+            }
+        });
+        match rt.block_on(tokio::time::timeout(timeout, gs.changed())) {
+            Ok(Ok(())) => (),
+            Ok(Err(_)) => return Err(ClientError::Closed),
+            Err(_) => return Err(ClientError::Timeout)
+        }
+
+        Ok(Self {
+            key: key.to_string(),
+            rt,
+            ctrl_socket: Mutex::new(ctrl_socket),
+            gs
+        })
+    }
+    /// Sends a request to the control socket and parses the response
+    fn request(&self, team: &str, number: u8, cmd: Vec<Value>) -> Result<Value, ClientError> {
+        let req = serde_json::to_vec(&json!([self.key, team, number, cmd]))?;
+        let res = self.rt.block_on(async {
+            let mut socket = self.ctrl_socket.lock().await;
+            socket.send(req.into()).await?;
+            socket.recv().await
+        })?;
+        let (success, msg): (Value, Value) = serde_json::from_slice(res.get(0).map(|b| b.as_ref()).unwrap_or_default())?;
+        match success {
+            Value::Bool(true) => Ok(msg),
+            _ => Err(ClientError::Refused(msg.as_str().map(|s| s.to_string()).unwrap_or_else(|| msg.to_string())))
+        }
+    }
+    /// Last game state received
+    pub fn game_state(&self) -> GameState {
+        self.gs.borrow().clone()
+    }
+    pub fn ball(&self) -> Option<Point2<f64>> {
+        self.gs.borrow().ball
+    }
+    pub fn robot(&self, r: Robot) -> ClientRobot<'_> {
+        ClientRobot {
+            client: self,
+            robot: r
+        }
+    }
+    pub fn blue1(&self) -> ClientRobot<'_> {
+        self.robot(Robot::Blue1)
+    }
+    pub fn blue2(&self) -> ClientRobot<'_> {
+        self.robot(Robot::Blue2)
+    }
+    pub fn green1(&self) -> ClientRobot<'_> {
+        self.robot(Robot::Green1)
+    }
+    pub fn green2(&self) -> ClientRobot<'_> {
+        self.robot(Robot::Green2)
+    }
+    /// Field description (only available with the simulation)
+    pub fn geometry(&self) -> Result<Geometry, ClientError> {
+        Ok(serde_json::from_value(self.request("geometry", 0, Vec::new())?)?)
+    }
+    /// Replaces the debug drawings of `namespace` shown by the simulator's GUI (see `debug`). An empty list clears them
+    pub fn draw_debug(&self, team: Team, namespace: &str, primitives: &[Primitive]) -> Result<(), ClientError> {
+        let team = match team {
+            Team::Blue => "blue",
+            Team::Green => "green"
+        };
+        self.request(team, 0, vec![json!("debug"), json!(namespace), serde_json::to_value(primitives)?]).map(|_| ())
+    }
+    /// Waits for the next game state. Returns `ClientError::Closed` if the game state socket stopped
+    pub fn wait_update(&mut self) -> Result<(), ClientError> {
+        self.rt.block_on(self.gs.changed()).map_err(|_| ClientError::Closed)
+    }
+    /// Calls `f(client, dt)` each time a new game state is received. `dt` is the time since the previous call in seconds.
+    /// Only returns when the game state socket stops, with `ClientError::Closed`
+    pub fn on_update<F: FnMut(&Client, f64)>(&mut self, mut f: F) -> Result<(), ClientError> {
+        let mut last = Instant::now();
+        loop {
+            self.wait_update()?;
+            f(self, last.elapsed().as_secs_f64());
+            last = Instant::now();
+        }
+    }
+}
+
+/// Handle to send commands to a robot, like `client.blue1` in python
+pub struct ClientRobot<'a> {
+    client: &'a Client,
+    robot: Robot,
+}
+impl ClientRobot<'_> {
+    fn team(&self) -> &'static str {
+        match self.robot.team() {
+            Team::Blue => "blue",
+            Team::Green => "green"
+        }
+    }
+    fn command(&self, cmd: Vec<Value>) -> Result<(), ClientError> {
+        self.client.request(self.team(), self.robot.number(), cmd).map(|_| ())
+    }
+    pub fn pose(&self) -> Pose {
+        self.client.gs.borrow().markers.get(self.robot).clone()
+    }
+    pub fn position(&self) -> Point2<f64> {
+        self.pose().position
+    }
+    pub fn orientation(&self) -> f64 {
+        self.pose().orientation
+    }
+    /// Speeds in the robot's frame (m/s, m/s, rad/s)
+    pub fn control(&self, dx: f64, dy: f64, dturn: f64) -> Result<(), ClientError> {
+        self.command(vec![json!("control"), json!(dx), json!(dy), json!(dturn)])
+    }
+    /// power between 0. and 1.
+    pub fn kick(&self, power: f64) -> Result<(), ClientError> {
+        self.command(vec![json!("kick"), json!(power)])
+    }
+    pub fn leds(&self, r: u8, g: u8, b: u8) -> Result<(), ClientError> {
+        self.command(vec![json!("leds"), json!(r), json!(g), json!(b)])
+    }
+    /// Goes to (x, y, orientation). If `wait` is false, sends only one control order and returns true if the robot has arrived
+    pub fn goto(&self, target: (f64, f64, f64), wait: bool) -> Result<bool, ClientError> {
+        let target = Pose {
+            position: Point2::new(target.0, target.1),
+            orientation: target.2
+        };
+        loop {
+            let (arrived, (x, y, r)) = self.pose().goto(&target);
+            if !wait {
+                self.control(x as f64, y as f64, r as f64)?;
+                return Ok(arrived)
+            }
+            if arrived {
+                self.control(0., 0., 0.)?;
+                return Ok(true)
+            }
+            self.control(x as f64, y as f64, r as f64)?;
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
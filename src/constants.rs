@@ -7,6 +7,10 @@ pub const FRAME_DURATION: usize = (DT * 1000.) as usize; // in ms
 pub const PENALTY_DURATION: usize = 5000 / FRAME_DURATION; // in frames
 pub const MATCH_DURATION: Duration = Duration::from_secs(600);
 pub const BALL_ABUSE_TIME: usize = (3.0/DT) as usize; // in frames
+pub const SHOOTOUT_ATTEMPT_TIME: usize = (10.0/DT) as usize; // in frames
+pub const SHOOTOUT_ROUNDS: usize = 5; // attempts of each team before the sudden death
+pub const DEFAULT_PUBLISH_RATE: usize = 60; // in Hz, like the real camera
+pub const DEFAULT_DECISION_RATE: usize = 60; // in Hz, for the in-process strategies
 
 
 /// Constants in simulation are multiplied because rapier bugs with small numbers
@@ -14,7 +18,7 @@ pub mod simu {
     use nalgebra::Point2;
     use super::real;
 
-    pub use super::{DT, FRAME_DURATION, PENALTY_DURATION, MATCH_DURATION};
+    pub use super::{DT, FRAME_DURATION, PENALTY_DURATION, MATCH_DURATION, DEFAULT_PUBLISH_RATE, DEFAULT_DECISION_RATE};
     pub use real::{DEFAULT_ROBOTS_ANGLE, BALL_RESTITUTION, BALL_DAMPING, ROBOT_DAMPING, ROBOT_ANGULAR_DAMPING, ROBOT_RESTITUTION};
 
     pub const MULTIPLIER: f64 = 10.;
@@ -77,7 +81,7 @@ pub mod real {
     use std::f64::consts::PI;
 
     use nalgebra::Point2;
-    pub use super::{DT, FRAME_DURATION, PENALTY_DURATION, MATCH_DURATION};
+    pub use super::{DT, FRAME_DURATION, PENALTY_DURATION, MATCH_DURATION, DEFAULT_PUBLISH_RATE, DEFAULT_DECISION_RATE};
 
     
 
@@ -101,6 +105,20 @@ pub mod real {
         Point2::new(FIELD.0*1.5/5., -FIELD.1/2.),
     ];
 
+    /// Penalty marks in front of the [negative x, positive x] goals, on the line of the dots
+    pub const PENALTY_MARKS: [Point2<f64>; 2] = [
+        Point2::new(-DOT_POS.0, 0.),
+        Point2::new(DOT_POS.0, 0.),
+    ];
+
+    /// Corners of the carpet, outside of the field, for the robots that don't play (scenarios, penalty shootouts). [blue1, blue2, green1, green2]
+    pub const PARKING_SPOTS: [Point2<f64>; 4] = [
+        Point2::new(-(FIELD.0 + MARGIN)/2., (FIELD.1 + MARGIN)/2.),
+        Point2::new(-(FIELD.0 + MARGIN)/2., -(FIELD.1 + MARGIN)/2.),
+        Point2::new((FIELD.0 + MARGIN)/2., (FIELD.1 + MARGIN)/2.),
+        Point2::new((FIELD.0 + MARGIN)/2., -(FIELD.1 + MARGIN)/2.),
+    ];
+
     pub const BALL_ABUSE_RADIUS: f64 = 0.25;
 
     pub const GOAL_HEIGHT: f64 = 0.6;
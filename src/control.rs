@@ -1,6 +1,8 @@
 #[cfg(feature = "serde")]
 use serde::{ser::SerializeTuple, Serialize};
 
+use crate::game_state::Geometry;
+
 #[derive(Debug)]
 pub enum CtrlRes {
     UnknownError,
@@ -9,6 +11,7 @@ pub enum CtrlRes {
     Preempted(String, u8, String),
     UnknownRobot(String, u8),
     UnknownCommand,
+    Geometry(Geometry),
     Ok
 }
 #[cfg(feature = "serde")]
@@ -53,6 +56,13 @@ impl Serialize for CtrlRes {
                 tup.serialize_element("Unknown command")?;
                 tup.end()
             },
+            &CtrlRes::Geometry(ref geometry) => {
+                // [True, {geometry}]
+                let mut tup = serializer.serialize_tuple(2)?;
+                tup.serialize_element(&true)?;
+                tup.serialize_element(geometry)?;
+                tup.end()
+            },
             &CtrlRes::Ok => {
                 // [True, "ok"]
                 let mut tup = serializer.serialize_tuple(2)?;
@@ -0,0 +1,119 @@
+//! Debug drawings of the strategies (targets, pass lanes, planned paths...) shown over the field by the GUI.
+//! They are grouped in layers : a team and a namespace. Sending a layer replaces its previous content
+//! and sending an empty layer clears it.
+//!
+//! The positions are in meters, in the frame of the game state.
+
+use nalgebra::Point2;
+
+use crate::game_state::Team;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Shape {
+    Point {
+        position: Point2<f64>,
+    },
+    Line {
+        from: Point2<f64>,
+        to: Point2<f64>,
+    },
+    Circle {
+        center: Point2<f64>,
+        radius: f64,
+    },
+    Arrow {
+        from: Point2<f64>,
+        to: Point2<f64>,
+    },
+    Text {
+        position: Point2<f64>,
+        text: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Primitive {
+    pub shape: Shape,
+    /// (r, g, b) between 0. and 1. The color of the team if None
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub color: Option<[f32; 3]>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layer {
+    pub team: Team,
+    pub namespace: String,
+    pub primitives: Vec<Primitive>,
+}
+
+/// Current layers, in the order of their first drawing
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DebugDrawings {
+    pub layers: Vec<Layer>,
+}
+impl DebugDrawings {
+    /// Replaces the layer with the same team and namespace, or removes it if it is empty
+    pub fn set(&mut self, layer: Layer) {
+        let existing = self.layers.iter().position(|l| l.team == layer.team && l.namespace == layer.namespace);
+        match (existing, layer.primitives.is_empty()) {
+            (Some(i), true) => {
+                self.layers.remove(i);
+            },
+            (Some(i), false) => self.layers[i] = layer,
+            (None, true) => (),
+            (None, false) => self.layers.push(layer)
+        }
+    }
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(team: Team, namespace: &str, points: usize) -> Layer {
+        Layer {
+            team,
+            namespace: namespace.to_string(),
+            primitives: (0..points).map(|i| Primitive {
+                shape: Shape::Point { position: Point2::new(i as f64 * 0.1, 0.) },
+                color: None
+            }).collect()
+        }
+    }
+
+    #[test]
+    fn set_replaces_the_layer_in_place() {
+        let mut drawings = DebugDrawings::default();
+        drawings.set(layer(Team::Blue, "targets", 1));
+        drawings.set(layer(Team::Blue, "paths", 1));
+        drawings.set(layer(Team::Blue, "targets", 3));
+        assert_eq!(drawings.layers, [layer(Team::Blue, "targets", 3), layer(Team::Blue, "paths", 1)]);
+    }
+
+    #[test]
+    fn layers_are_separated_by_team() {
+        let mut drawings = DebugDrawings::default();
+        drawings.set(layer(Team::Blue, "targets", 1));
+        drawings.set(layer(Team::Green, "targets", 2));
+        assert_eq!(drawings.layers.len(), 2);
+    }
+
+    #[test]
+    fn empty_layer_clears() {
+        let mut drawings = DebugDrawings::default();
+        drawings.set(layer(Team::Blue, "targets", 1));
+        drawings.set(layer(Team::Green, "targets", 1));
+        drawings.set(layer(Team::Blue, "targets", 0));
+        assert_eq!(drawings.layers, [layer(Team::Green, "targets", 1)]);
+        // Nothing to clear
+        drawings.set(layer(Team::Blue, "paths", 0));
+        assert_eq!(drawings.layers, [layer(Team::Green, "targets", 1)]);
+    }
+}
@@ -0,0 +1,257 @@
+//! Export of match recordings (see `record`) to csv tables for data analysis :
+//! - `frames.csv` : one row per recorded game state, with the last control command of each robot
+//! - `commands.csv` : one row per command
+//! - `events.csv` : goals, ball out of field, penalties, kicks, referee decisions and penalty shootouts
+//!
+//! The column names follow the fields of `GameState` (for example `markers.blue1.position.x`).
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::game_state::{GameState, Robot, Team};
+use crate::record::RecordEntry;
+use crate::referee::RefereeEvent;
+use crate::strategy::Command;
+
+const ROBOT_NAMES: [&str; 4] = ["blue1", "blue2", "green1", "green2"];
+
+/// Quotes the value if needed
+fn escape(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+struct Table {
+    writer: BufWriter<File>,
+}
+impl Table {
+    fn create(path: &Path, columns: &[String]) -> io::Result<Self> {
+        let mut table = Self {
+            writer: BufWriter::new(File::create(path)?)
+        };
+        table.row(columns)?;
+        Ok(table)
+    }
+    fn row(&mut self, values: &[String]) -> io::Result<()> {
+        let values: Vec<String> = values.iter().map(|v| escape(v)).collect();
+        writeln!(self.writer, "{}", values.join(","))
+    }
+}
+
+/// Empty cell for None
+fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn frame_columns() -> Vec<String> {
+    let mut columns: Vec<String> = ["t", "ball.x", "ball.y", "extended.timestamp", "extended.ball_velocity.x", "extended.ball_velocity.y"]
+        .into_iter().map(String::from).collect();
+    for name in ROBOT_NAMES {
+        for field in ["position.x", "position.y", "orientation"] {
+            columns.push(format!("markers.{}.{}", name, field));
+        }
+        for field in ["linear.x", "linear.y", "angular"] {
+            columns.push(format!("extended.velocities.{}.{}", name, field));
+        }
+        for field in ["x", "y", "r"] {
+            columns.push(format!("control.{}.{}", name, field));
+        }
+    }
+    for team in ["blue", "green"] {
+        for field in ["name", "score", "x_positive"] {
+            columns.push(format!("referee.teams.{}.{}", team, field));
+        }
+        for robot in ["one", "two"] {
+            for field in ["penalized", "penalized_remaining", "penalized_reason"] {
+                columns.push(format!("referee.teams.{}.robots.{}.{}", team, robot, field));
+            }
+        }
+    }
+    for field in ["game_is_running", "game_paused", "halftime_is_running", "timer", "game_state_msg"] {
+        columns.push(format!("referee.{}", field));
+    }
+    columns
+}
+
+fn frame_row(t: usize, gs: &GameState, controls: &[(f32, f32, f32); 4]) -> Vec<String> {
+    let extended = gs.extended.as_ref();
+    let mut row = vec![
+        t.to_string(),
+        opt(gs.ball.map(|b| b.x)),
+        opt(gs.ball.map(|b| b.y)),
+        opt(extended.map(|e| e.timestamp)),
+        opt(extended.map(|e| e.ball_velocity.x)),
+        opt(extended.map(|e| e.ball_velocity.y)),
+    ];
+    for r in Robot::all() {
+        let pose = gs.markers.get(r);
+        row.extend([pose.position.x, pose.position.y, pose.orientation].map(|v| v.to_string()));
+        let v = extended.map(|e| e.velocities.get(r));
+        row.extend([opt(v.map(|v| v.linear.x)), opt(v.map(|v| v.linear.y)), opt(v.map(|v| v.angular))]);
+        let (x, y, rot) = controls[r as usize];
+        row.extend([x, y, rot].map(|v| v.to_string()));
+    }
+    for team in Team::all() {
+        let referee_team = gs.referee.teams.get(team);
+        row.extend([referee_team.name.clone(), referee_team.score.to_string(), referee_team.x_positive.to_string()]);
+        for number in [1, 2] {
+            let robot = referee_team.robots.get(number);
+            row.extend([robot.penalized.to_string(), opt(robot.penalized_remaining), opt(robot.penalized_reason.clone())]);
+        }
+    }
+    let referee = &gs.referee;
+    row.extend([
+        referee.game_is_running.to_string(),
+        referee.game_paused.to_string(),
+        referee.halftime_is_running.to_string(),
+        referee.timer.to_string(),
+        referee.game_state_msg.clone(),
+    ]);
+    row
+}
+
+/// Writes `frames.csv`, `commands.csv` and `events.csv` in `dir`
+pub fn export_csv(entries: &[RecordEntry], dir: impl AsRef<Path>) -> io::Result<()> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    let mut frames = Table::create(&dir.join("frames.csv"), &frame_columns())?;
+    let mut commands = Table::create(&dir.join("commands.csv"), &["t", "robot", "command", "x", "y", "r", "power"].map(String::from))?;
+    let mut events = Table::create(&dir.join("events.csv"), &["t", "event", "team", "robot", "reason", "power"].map(String::from))?;
+
+    // Last control command of each robot
+    let mut controls = [(0., 0., 0.); 4];
+    for entry in entries {
+        match entry {
+            RecordEntry::Config(_) | RecordEntry::Debug { .. } | RecordEntry::Intervention { .. } => (),
+            // The robots driven by hand have no command but a control
+            RecordEntry::Drive { t, robot, control, kick } => {
+                controls[*robot as usize] = control.unwrap_or_default();
+                if let Some(power) = kick {
+                    events.row(&[t.to_string(), "kick".to_string(), team_name(robot.team()), ROBOT_NAMES[*robot as usize].to_string(), String::new(), power.to_string()])?;
+                }
+            },
+            RecordEntry::Frame { t, state } => frames.row(&frame_row(*t, state, &controls))?,
+            RecordEntry::Command { t, command, .. } => match *command {
+                Command::Control(r, control) => {
+                    controls[r as usize] = control;
+                    let (x, y, rot) = control;
+                    commands.row(&[t.to_string(), ROBOT_NAMES[r as usize].to_string(), "control".to_string(), x.to_string(), y.to_string(), rot.to_string(), String::new()])?;
+                },
+                Command::Kick(r, power) => {
+                    let robot = ROBOT_NAMES[r as usize].to_string();
+                    commands.row(&[t.to_string(), robot.clone(), "kick".to_string(), String::new(), String::new(), String::new(), power.to_string()])?;
+                    events.row(&[t.to_string(), "kick".to_string(), team_name(r.team()), robot, String::new(), power.to_string()])?;
+                }
+            },
+            RecordEntry::Event { t, event } => {
+                let row = match event {
+                    RefereeEvent::Goal(team) => ["goal".to_string(), team_name(*team), String::new(), String::new()],
+                    RefereeEvent::BallOut => ["ball_out".to_string(), String::new(), String::new(), String::new()],
+                    RefereeEvent::Penalty(r, reason) => ["penalty".to_string(), team_name(r.team()), ROBOT_NAMES[*r as usize].to_string(), reason.clone()],
+                    RefereeEvent::PenaltyEnd(r) => ["penalty_end".to_string(), team_name(r.team()), ROBOT_NAMES[*r as usize].to_string(), String::new()],
+                    RefereeEvent::Unpenalized(r) => ["unpenalized".to_string(), team_name(r.team()), ROBOT_NAMES[*r as usize].to_string(), String::new()],
+                    RefereeEvent::GoalCancelled(team) => ["goal_cancelled".to_string(), team_name(*team), String::new(), String::new()],
+                    RefereeEvent::Start => ["start".to_string(), String::new(), String::new(), String::new()],
+                    RefereeEvent::Pause => ["pause".to_string(), String::new(), String::new(), String::new()],
+                    RefereeEvent::Resume => ["resume".to_string(), String::new(), String::new(), String::new()],
+                    RefereeEvent::Halftime => ["halftime".to_string(), String::new(), String::new(), String::new()],
+                    RefereeEvent::Shootout(team) => ["shootout".to_string(), team_name(*team), String::new(), String::new()],
+                    RefereeEvent::ShootoutAttempt(team, scored) => {
+                        let event = if *scored {"shootout_goal"} else {"shootout_miss"};
+                        [event.to_string(), team_name(*team), ROBOT_NAMES[team.robot(2) as usize].to_string(), String::new()]
+                    },
+                    RefereeEvent::ShootoutWinner(team) => ["shootout_winner".to_string(), team_name(*team), String::new(), String::new()],
+                };
+                let [event, team, robot, reason] = row;
+                events.row(&[t.to_string(), event, team, robot, reason, String::new()])?;
+            }
+        }
+    }
+    for mut table in [frames, commands, events] {
+        table.writer.flush()?;
+    }
+    Ok(())
+}
+
+fn team_name(team: Team) -> String {
+    match team {
+        Team::Blue => "blue",
+        Team::Green => "green"
+    }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+
+    fn state(ball: (f64, f64)) -> GameState {
+        let mut gs = GameState::default();
+        gs.ball = Some(Point2::new(ball.0, ball.1));
+        gs.referee.teams.blue.name = "Blue, team".to_string();
+        gs
+    }
+
+    /// Lines of a csv file
+    fn lines(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path).unwrap().lines().map(String::from).collect()
+    }
+
+    fn column(header: &str, name: &str) -> usize {
+        header.split(',').position(|c| c == name).unwrap_or_else(|| panic!("No column {}", name))
+    }
+
+    #[test]
+    fn frame_rows_match_the_columns() {
+        let controls = [(0., 0., 0.); 4];
+        assert_eq!(frame_row(0, &state((0., 0.)), &controls).len(), frame_columns().len());
+    }
+
+    #[test]
+    fn export_writes_the_columns_and_the_last_controls() {
+        let dir = std::env::temp_dir().join(format!("rsk_export_{}", std::process::id()));
+        let entries = [
+            RecordEntry::Frame { t: 0, state: state((0.25, -0.5)) },
+            RecordEntry::Command { t: 3, key: String::new(), command: Command::Control(Robot::Blue2, (0.5, 0., 1.)) },
+            RecordEntry::Command { t: 4, key: String::new(), command: Command::Kick(Robot::Green1, 0.75) },
+            RecordEntry::Event { t: 5, event: RefereeEvent::Goal(Team::Green) },
+            RecordEntry::Frame { t: 10, state: state((0.5, 0.)) },
+        ];
+        export_csv(&entries, &dir).unwrap();
+
+        let frames = lines(&dir.join("frames.csv"));
+        let header = &frames[0];
+        assert!(header.starts_with("t,ball.x,ball.y,extended.timestamp"));
+        assert_eq!(frames.len(), 3);
+        let second: Vec<&str> = frames[2].split(',').collect();
+        assert_eq!(second[column(header, "t")], "10");
+        assert_eq!(second[column(header, "ball.x")], "0.5");
+        assert_eq!(second[column(header, "control.blue2.x")], "0.5");
+        assert_eq!(second[column(header, "control.blue2.r")], "1");
+        assert_eq!(second[column(header, "control.green1.x")], "0");
+        // Without the extended state, its cells are empty
+        assert_eq!(second[column(header, "extended.timestamp")], "");
+        // The name with a comma is quoted
+        assert!(frames[2].contains("\"Blue, team\""));
+
+        let commands = lines(&dir.join("commands.csv"));
+        assert_eq!(commands, [
+            "t,robot,command,x,y,r,power",
+            "3,blue2,control,0.5,0,1,",
+            "4,green1,kick,,,,0.75",
+        ]);
+
+        let events = lines(&dir.join("events.csv"));
+        assert_eq!(events, [
+            "t,event,team,robot,reason,power",
+            "4,kick,green,green1,,0.75",
+            "5,goal,green,,,",
+        ]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
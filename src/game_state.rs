@@ -84,6 +84,20 @@ pub struct Pose {
     pub position: Point<f64>,
     pub orientation: f64,
 }
+impl Pose {
+    /// Control (x, y, rotation) in the robot's frame to reach `target`, like the goto of the official client.
+    /// The bool is true if the robot has arrived
+    pub fn goto(&self, target: &Pose) -> (bool, (f32, f32, f32)) {
+        let (sin, cos) = self.orientation.sin_cos();
+        let d = target.position - self.position;
+        // Target in the robot's frame
+        let x = cos*d.x + sin*d.y;
+        let y = -sin*d.x + cos*d.y;
+        let r = (target.orientation - self.orientation + PI).rem_euclid(2.*PI) - PI;
+        let arrived = (x*x + y*y).sqrt() < 0.02 && r.abs() < 3f64.to_radians();
+        (arrived, ((1.5*x) as f32, (1.5*y) as f32, (1.5*r) as f32))
+    }
+}
 impl Add for &Pose {
     type Output = Pose;
     fn add(self, rhs: Self) -> Self::Output {
@@ -111,6 +125,16 @@ pub struct Markers {
     pub green1: Pose,
     pub green2: Pose,
 }
+impl Markers {
+    pub fn get(&self, r: Robot) -> &Pose {
+        match r {
+            Robot::Blue1 => &self.blue1,
+            Robot::Blue2 => &self.blue2,
+            Robot::Green1 => &self.green1,
+            Robot::Green2 => &self.green2,
+        }
+    }
+}
 impl Default for Markers {
     fn default() -> Self {
         Self {
@@ -151,6 +175,16 @@ pub struct RefereeTeamRobots {
     #[cfg_attr(feature = "serde", serde(rename = "2"))]
     pub two: RefereeTeamRobot,
 }
+impl RefereeTeamRobots {
+    /// Robot from its number (1 or 2)
+    pub fn get(&self, number: u8) -> &RefereeTeamRobot {
+        if number == 1 {
+            &self.one
+        } else {
+            &self.two
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -177,6 +211,17 @@ pub struct RefereeTeams {
     pub green: RefereeTeam,
     pub blue: RefereeTeam,
 }
+impl RefereeTeams {
+    pub fn get(&self, team: Team) -> &RefereeTeam {
+        match team {
+            Team::Blue => &self.blue,
+            Team::Green => &self.green
+        }
+    }
+    pub fn robot(&self, r: Robot) -> &RefereeTeamRobot {
+        self.get(r.team()).robots.get(r.number())
+    }
+}
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -202,6 +247,55 @@ impl Default for Referee {
     }
 }
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Velocity {
+    /// In m/s
+    pub linear: Vector<f64>,
+    /// In rad/s
+    pub angular: f64,
+}
+impl Default for Velocity {
+    fn default() -> Self {
+        Self {
+            linear: Vector::new(0., 0.),
+            angular: 0.
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Velocities {
+    pub blue1: Velocity,
+    pub blue2: Velocity,
+    pub green1: Velocity,
+    pub green2: Velocity,
+}
+impl Velocities {
+    pub fn get(&self, r: Robot) -> &Velocity {
+        match r {
+            Robot::Blue1 => &self.blue1,
+            Robot::Blue2 => &self.blue2,
+            Robot::Green1 => &self.green1,
+            Robot::Green2 => &self.green2,
+        }
+    }
+}
+
+/// Informations that are not in the official game state. Only sent if the client asked for it
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Extended {
+    /// Frame of the simulation
+    pub t: usize,
+    /// Simulated time since the start, in seconds
+    pub timestamp: f64,
+    /// In m/s
+    pub ball_velocity: Vector<f64>,
+    pub velocities: Velocities,
+}
+
 /// Representation of the game given to the client
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -209,13 +303,81 @@ pub struct GameState {
     pub ball: Option<Point<f64>>,
     pub markers: Markers,
     pub referee: Referee,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub extended: Option<Extended>,
 }
 impl Default for GameState {
     fn default() -> Self {
         Self {
             ball: Some(Point::new(0., 0.)),
             markers: Markers::default(),
-            referee: Referee::default()
+            referee: Referee::default(),
+            extended: None
+        }
+    }
+}
+#[cfg(feature = "json")]
+impl GameState {
+    /// Json sent to the clients. Without the extended section, it's the same as the official game controller
+    pub fn to_json(&self) -> Vec<u8> {
+        self.json(None)
+    }
+    /// Json sent to the clients in lockstep mode, with the frame that the controllers must acknowledge
+    pub fn to_json_with_frame(&self, frame: usize) -> Vec<u8> {
+        self.json(Some(frame))
+    }
+    fn json(&self, frame: Option<usize>) -> Vec<u8> {
+        // bitcode doesn't support skip_serializing_if so we only use it here
+        #[derive(serde::Serialize)]
+        struct Json<'a> {
+            ball: &'a Option<Point<f64>>,
+            markers: &'a Markers,
+            referee: &'a Referee,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            extended: Option<&'a Extended>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            frame: Option<usize>,
+        }
+        serde_json::to_vec(&Json {
+            ball: &self.ball,
+            markers: &self.markers,
+            referee: &self.referee,
+            extended: self.extended.as_ref(),
+            frame
+        }).unwrap()
+    }
+}
+
+/// Description of the field given to the client, in meters
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Geometry {
+    pub field: (f64, f64),
+    pub carpet: (f64, f64),
+    pub defense_area: (f64, f64),
+    pub center_circle_radius: f64,
+    pub goal_height: f64,
+    /// (top, bottom)
+    pub blue_goal: (Point<f64>, Point<f64>),
+    /// (top, bottom)
+    pub green_goal: (Point<f64>, Point<f64>),
+    pub penalty_spots: Vec<Point<f64>>,
+    pub robot_radius: f64,
+    pub ball_radius: f64,
+}
+impl Default for Geometry {
+    fn default() -> Self {
+        Self {
+            field: FIELD,
+            carpet: CARPET,
+            defense_area: DEFENSE_AREA,
+            center_circle_radius: CENTER_CIRCLE_RADIUS,
+            goal_height: GOAL_HEIGHT,
+            blue_goal: BLUE_GOAL,
+            green_goal: GREEN_GOAL,
+            penalty_spots: PENALTY_SPOTS.to_vec(),
+            robot_radius: ROBOT_RADIUS,
+            ball_radius: BALL_RADIUS
         }
     }
 }
@@ -234,6 +396,64 @@ impl Robot {
     pub const fn all() -> [Self; 4] {
         [Self::Blue1, Self::Blue2, Self::Green1, Self::Green2]
     }
+    /// Robot from its name in the game state ("blue1", "blue2", "green1" or "green2")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "blue1" => Some(Self::Blue1),
+            "blue2" => Some(Self::Blue2),
+            "green1" => Some(Self::Green1),
+            "green2" => Some(Self::Green2),
+            _ => None
+        }
+    }
+    /// Number of the robot in its team (1 or 2)
+    pub const fn number(self) -> u8 {
+        self as u8 % 2 + 1
+    }
+    pub const fn team(self) -> Team {
+        match self {
+            Self::Blue1 | Self::Blue2 => Team::Blue,
+            Self::Green1 | Self::Green2 => Team::Green
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// `team as usize` can be used to index arrays of things related to teams ([blue, green])
+pub enum Team {
+    Blue = 0,
+    Green = 1,
+}
+impl Team {
+    pub const fn all() -> [Self; 2] {
+        [Self::Blue, Self::Green]
+    }
+    /// Robot from its number (1 or 2)
+    pub const fn robot(self, number: u8) -> Robot {
+        match (self, number) {
+            (Self::Blue, 1) => Robot::Blue1,
+            (Self::Blue, _) => Robot::Blue2,
+            (Self::Green, 1) => Robot::Green1,
+            (Self::Green, _) => Robot::Green2
+        }
+    }
+    pub const fn opponent(self) -> Self {
+        match self {
+            Self::Blue => Self::Green,
+            Self::Green => Self::Blue
+        }
+    }
+    /// Center of the goal defended by the team, on the side given by the referee (`x_positive`)
+    pub fn goal(self, referee: &Referee) -> Point<f64> {
+        // The goals are named after the team that defends them when the sides are not swapped
+        let (top, bottom) = if referee.teams.get(self).x_positive {
+            GREEN_GOAL
+        } else {
+            BLUE_GOAL
+        };
+        nalgebra::center(&top, &bottom)
+    }
 }
 
 #[derive(Default)]
@@ -243,5 +463,17 @@ pub struct RobotTasks {
     /// (x, y, rotation)
     pub control: (f32, f32, f32),
     /// strength
-    pub kick: Option<f32>
+    pub kick: Option<f32>,
+    /// Reason why the robot is driven by the simulator (by hand from the GUI, by a scenario...).
+    /// The commands of the controllers and strategies are refused
+    pub driven: Option<&'static str>,
+}
+impl RobotTasks {
+    /// Reason why the commands of the controllers are refused, if any
+    pub fn preemption(&self) -> Option<&'static str> {
+        match self.penalty {
+            Some((reason, _, _)) => Some(reason),
+            None => self.driven
+        }
+    }
 }
\ No newline at end of file
@@ -6,7 +6,7 @@ use tracing::{info, error};
 use wasm_sockets::{ConnectionStatus, EventClient, Message};
 use wasm_timer::Instant;
 
-use crate::game_state::{GameState, Robot, RobotTasks};
+use crate::game_state::{GameState, Geometry, Robot, RobotTasks};
 use crate::http::default::{ClientMsg, ServerMsg};
 use crate::native;
 
@@ -43,12 +43,16 @@ pub fn main() {
 
 pub struct Control {
     socket: EventClient,
-    last_publish: Instant
+    last_publish: Instant,
+    /// Last game state of the session, when another client runs it and this one only watches
+    spectated: Rc<RefCell<Option<GameState>>>
 }
 impl Control {
     pub fn new(keys: [String; 2], tasks: Rc<RefCell<[RobotTasks; 4]>>, session_id: &str) -> Self {
         let mut socket = EventClient::new(&format!("ws://{}/{}", HOST, session_id)).unwrap();
 
+        let spectated = Rc::new(RefCell::new(None));
+
         let sid = session_id.to_string();
         socket.set_on_connection(Some(Box::new(move |socket| {
             socket.send_binary(
@@ -57,6 +61,18 @@ impl Control {
             info!("Socket connected");
         })));
 
+        // The server closes the socket of the spectators when the client that runs the session leaves :
+        // the page then shows its own session again
+        let state = spectated.clone();
+        socket.set_on_close(Some(Box::new(move |_| {
+            if state.borrow_mut().take().is_some() {
+                info!("The spectated session ended");
+            } else {
+                info!("Socket closed");
+            }
+        })));
+
+        let state = spectated.clone();
         socket.set_on_message(Some(Box::new(move |socket, msg| {
             let req = match msg {
                 Message::Text(string) => string,
@@ -84,9 +100,9 @@ impl Control {
                             } {
                                 let mut tasks = tasks.borrow_mut();
                                 let mut preempted = false;
-                                if let Some((r, _, _)) = tasks[r as usize].penalty {
+                                if let Some(reason) = tasks[r as usize].preemption() {
                                     preempted = true;
-                                    res = CtrlRes::Preempted(team, number, r.to_string());
+                                    res = CtrlRes::Preempted(team, number, reason.to_string());
                                 }
                                 if !preempted {
                                     match cmd.len() {
@@ -100,6 +116,8 @@ impl Control {
                                                     );
                                                     res = CtrlRes::Ok;
                                                 }
+                                                // The simulated robots have no leds
+                                                "leds" => res = CtrlRes::Ok,
                                                 _ => res = CtrlRes::UnknownCommand
                                             },
                                             _ => res = CtrlRes::UnknownCommand
@@ -122,8 +140,14 @@ impl Control {
                             }
                         }
                     },
+                    "geometry" => res = CtrlRes::Geometry(Geometry::default()),
                     "ball" => todo!(),
                     _ => {dbg!(key, team, number, cmd);}
+                },
+                // Only sent to the spectators, there is nothing to answer
+                ServerMsg::GameState(gs) => {
+                    *state.borrow_mut() = Some(gs);
+                    return
                 }
             }
             let res = serde_json::to_vec(&res).unwrap();
@@ -134,11 +158,20 @@ impl Control {
 
         Self {
             socket,
-            last_publish: Instant::now()
+            last_publish: Instant::now(),
+            spectated
         }
     }
-    /// Send new game state to client
-    pub fn publish(&mut self, gs: GameState) {
+    /// Last game state received from the server if this client is a spectator of the session
+    pub fn spectated(&self) -> Option<GameState> {
+        self.spectated.borrow().clone()
+    }
+    /// Send new game state to client. There is no lockstep mode in the browser, the frame is not used
+    pub fn publish(&mut self, gs: GameState, _t: usize) {
+        // The server ignores the game states of the spectators
+        if self.spectated.borrow().is_some() {
+            return
+        }
         if self.last_publish.elapsed() > PUBLISH_RATE {
             if let ConnectionStatus::Connected = self.socket.status.borrow().clone() {
                 self.socket.send_binary(
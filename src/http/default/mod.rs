@@ -18,7 +18,9 @@ pub enum ClientMsg {
 
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ServerMsg {
-    Ctrl(String, String, u8, Vec<Value>)
+    Ctrl(String, String, u8, Vec<Value>),
+    /// Game state of the client that runs the session, sent to the spectators
+    GameState(GameState)
 }
 
 pub const WS_PORT: u16 = 1234;
\ No newline at end of file
@@ -1,9 +1,9 @@
 use std::{sync::Arc, time::Duration};
-use dashmap::DashMap;
-use futures_util::{SinkExt, StreamExt};
+use dashmap::{mapref::entry::Entry, DashMap};
+use futures_util::{stream::{SplitSink, SplitStream}, SinkExt, StreamExt};
 use serde_json::Value;
-use tokio::{net::TcpListener, sync::mpsc, time::sleep};
-use tokio_tungstenite::tungstenite::Message;
+use tokio::{net::{TcpListener, TcpStream}, sync::{broadcast, mpsc}, time::sleep};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 use tracing::{error, info, warn};
 use crate::http::default::{ClientMsg, ServerMsg};
 use crate::game_state::GameState;
@@ -14,6 +14,19 @@ use super::WS_PORT;
 mod zeromq;
 use zeromq::{prelude::*, util::PeerIdentity};
 
+/// Game states kept for a spectator that reads slower than the owner of the session publishes
+const SPECTATOR_BUFFER: usize = 16;
+
+/// Connection of the client that runs the simulation of a session
+struct Session {
+    /// (team, number, command) forwarded to the websocket
+    ctrl_sender: mpsc::UnboundedSender<(String, u8, Vec<Value>)>,
+    /// response in bytes
+    res_receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+    /// Game states of the session in json (`ServerMsg::GameState`), for the spectators
+    spectators: broadcast::Sender<String>,
+}
+
 /// Sorry, this part of the code is ugly. If you want me to clean it and document it, ask Arkitu
 pub async fn main() {
     // Host the page and wasm file
@@ -21,14 +34,8 @@ pub async fn main() {
         "./target/wasm32-unknown-unknown/debug/rsk-simulation.wasm".to_string(),
     ));
 
-    // session_id --> (sender, receiver)
-    let ctrl_sessions = Arc::new(DashMap::<
-            String, // session's id
-            (
-                mpsc::UnboundedSender<(String, u8, Vec<Value>)>, // (team, number, command)
-                mpsc::UnboundedReceiver<Vec<u8>> // response in bytes
-            )
-        >::new());
+    // session_id --> session
+    let ctrl_sessions = Arc::new(DashMap::<String, Session>::new());
 
     let state_socket = zeromq::PubSocket::new();
 
@@ -62,16 +69,15 @@ pub async fn main() {
             };
             
             let res = match ctrls.get_mut(&key) {
-                Some(mut ctrl) => {
+                Some(mut session) => {
                     let ctrl_id = socket.current_request.clone().unwrap();
                     if !matched_pairs.contains(&ctrl_id) {
                         // Set the session for subscriber
                         subscribers_session.insert(pairs.get(&ctrl_id).unwrap().clone(), key);
                         matched_pairs.push(ctrl_id);
                     }
-                    let (_, (sender, receiver)) = ctrl.pair_mut();
-                    sender.send((team, number, cmd)).unwrap();
-                    receiver.recv().await.unwrap()
+                    session.ctrl_sender.send((team, number, cmd)).unwrap();
+                    session.res_receiver.recv().await.unwrap()
                 },
                 None => serde_json::to_vec(&CtrlRes::BadKey("you must put your session's id in your key".to_string())).unwrap()
             };
@@ -92,9 +98,9 @@ pub async fn main() {
     // Lobby
     let state = state_socket.clone();
     tokio::spawn(async move {
-        let json = serde_json::to_string(&GameState::default()).unwrap();
+        let json = GameState::default().to_json();
         loop {
-            state.send(("".to_string(), json.as_bytes().to_vec())).unwrap();
+            state.send(("".to_string(), json.clone())).unwrap();
             sleep(Duration::from_millis(500)).await;
         }
     });
@@ -123,12 +129,25 @@ pub async fn main() {
 
             let (snd, mut ctrl_receiver) = mpsc::unbounded_channel();
             let (ctrl_sender, rcv) = mpsc::unbounded_channel();
+            let (spectators, _) = broadcast::channel(SPECTATOR_BUFFER);
 
-            // TODO : if id already exists, lauch in spectator mode
-            ctrl_sessions.insert(session_id.clone(), (
-                snd,
-                rcv
-            ));
+            // The other clients with the id of a running session only watch it
+            let states = match ctrl_sessions.entry(session_id.clone()) {
+                Entry::Occupied(session) => Some(session.get().spectators.subscribe()),
+                Entry::Vacant(entry) => {
+                    entry.insert(Session {
+                        ctrl_sender: snd,
+                        res_receiver: rcv,
+                        spectators: spectators.clone()
+                    });
+                    None
+                }
+            };
+            if let Some(states) = states {
+                info!(target: "ws", "{} watches the session {} as spectator", addr, session_id);
+                spectate(states, ws_write, ws_read).await;
+                return
+            }
 
             let (res_sender, mut res_receiver) = mpsc::unbounded_channel();
 
@@ -144,8 +163,12 @@ pub async fn main() {
                                     return
                                 }
                                 ClientMsg::GameState(gs) => {
-                                    let json = serde_json::to_vec(&gs).unwrap();
+                                    let json = gs.to_json();
                                     state_socket.send((s_id.clone(), json)).unwrap();
+                                    if spectators.receiver_count() > 0 {
+                                        // Fails only if the spectators left in the meantime
+                                        let _ = spectators.send(serde_json::to_string(&ServerMsg::GameState(gs)).unwrap());
+                                    }
                                 },
                                 ClientMsg::CtrlRes(res) => {
                                     res_sender.send(res).unwrap();
@@ -191,4 +214,37 @@ pub async fn main() {
             });
         });
     }
-}
\ No newline at end of file
+}
+
+/// Sends the game states of a session to a spectator until the owner of the session or the spectator leaves.
+/// The messages of the spectator are ignored : it can't control the robots
+async fn spectate(
+    mut states: broadcast::Receiver<String>,
+    mut ws_write: SplitSink<WebSocketStream<TcpStream>, Message>,
+    mut ws_read: SplitStream<WebSocketStream<TcpStream>>
+) {
+    loop {
+        tokio::select! {
+            state = states.recv() => match state {
+                Ok(json) => if let Err(e) = ws_write.send(Message::Text(json)).await {
+                    error!(target: "ws", "Error when sending msg to spectator : {}", e);
+                    return
+                },
+                // The oldest game states are skipped
+                Err(broadcast::error::RecvError::Lagged(_)) => (),
+                Err(broadcast::error::RecvError::Closed) => {
+                    info!(target: "ws", "Spectated session closed");
+                    let _ = ws_write.send(Message::Close(None)).await;
+                    return
+                }
+            },
+            msg = ws_read.next() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                    info!(target: "ws", "spectator socket closed");
+                    return
+                },
+                Some(Ok(_)) => ()
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Library part of the crate, for the programs that use the simulation (for example Rust strategies or reinforcement learning).
+//! The binary (see main.rs) is built on top of it
+
+pub mod constants;
+pub mod game_state;
+pub mod debug;
+pub mod native;
+
+#[cfg(feature = "client")]
+pub mod client;
+
+#[cfg(feature = "referee")]
+pub mod referee;
+
+#[cfg(feature = "simulation")]
+pub mod simulation;
+
+#[cfg(feature = "native_gc")]
+pub mod strategy;
+
+#[cfg(all(feature = "rl", not(target_arch = "wasm32")))]
+pub mod rl;
+
+#[cfg(all(feature = "record", not(target_arch = "wasm32")))]
+pub mod record;
+#[cfg(all(feature = "record", not(target_arch = "wasm32")))]
+pub mod export;
+
+#[cfg(any(feature = "native_gui", all(any(feature = "rl", feature = "record"), not(target_arch = "wasm32"))))]
+pub mod render;
+
+#[cfg(any(feature = "alternative_http_client", feature = "alternative_http_server", feature = "http_client", feature = "http_server"))]
+pub mod http;
+
+#[cfg(feature = "control")]
+mod control;
+
+#[cfg(feature = "wasm_server_runner")]
+mod wasm_server_runner;
+
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
+mod python;
+
+#[cfg(all(feature = "native_control", not(target_arch = "wasm32")))]
+pub type Control = native::control::Control;
+#[cfg(all(feature = "native_gc", not(target_arch = "wasm32")))]
+pub type GC = native::gc::GC;
+
+#[cfg(all(feature = "http_client", target_arch = "wasm32"))]
+pub type Control = http::default::client::Control;
+#[cfg(all(feature = "http_client", target_arch = "wasm32"))]
+pub type GC = native::gc::GC;
+
+#[cfg(all(feature = "alternative_http_client", target_arch = "wasm32"))]
+pub type GC = http::alternative::client::GC;
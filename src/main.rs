@@ -1,36 +1,160 @@
-mod constants;
-mod game_state;
-mod native;
-
-#[cfg(feature = "referee")]
-mod referee;
-
-#[cfg(feature = "simulation")]
-mod simulation;
-
-#[cfg(any(feature = "alternative_http_client", feature = "alternative_http_server", feature = "http_client", feature = "http_server"))]
-mod http;
-
-#[cfg(feature = "control")]
-mod control;
-
-#[cfg(feature = "wasm_server_runner")]
-mod wasm_server_runner;
-
 #[cfg(all(feature = "native", not(target_arch = "wasm32")))]
-pub type Control = native::control::Control;
+mod cli;
+
 #[cfg(all(feature = "native", not(target_arch = "wasm32")))]
-pub type GC = native::gc::GC;
+use rsk_simulation::{constants, export, game_state, native, record, render, simulation, strategy, GC};
+
 #[cfg(all(feature = "native", not(target_arch = "wasm32")))]
 fn main() {
     tracing_subscriber::fmt::fmt()
         .without_time()
         .init();
-    let gc = native::gc::GC::new("".to_string(), "".to_string(), "".to_string(), "".to_string(), false);
+    let args = cli::Args::parse();
+    if let Some((path, dir)) = &args.export {
+        let entries = record::read(path).expect("Can't read the recording");
+        export::export_csv(&entries, dir).expect("Can't write the csv files");
+        return
+    }
+    if let Some((path, output)) = &args.render {
+        let entries = record::read(path).expect("Can't read the recording");
+        let frames = render::recording_frames(&entries, args.render_fps);
+        if output.ends_with(".svg") {
+            // Each frame is shown until the next one
+            let frames: Vec<_> = frames.iter().enumerate().map(|(i, (t, gs, debug))| {
+                let next = frames.get(i + 1).map_or(*t, |(next, _, _)| *next);
+                (((next - t) as f64 * constants::DT).max(1. / args.render_fps), gs.clone(), debug.clone())
+            }).collect();
+            std::fs::write(output, render::render_animated_svg(&frames)).expect("Can't write the animation");
+        } else {
+            std::fs::create_dir_all(output).expect("Can't create the directory");
+            for (i, (_, gs, debug)) in frames.iter().enumerate() {
+                let file = std::path::Path::new(output).join(format!("frame_{:06}.svg", i));
+                std::fs::write(file, render::render_svg(gs, debug)).expect("Can't write the frame");
+            }
+        }
+        return
+    }
+    if let Some(path) = &args.replay {
+        let entries = record::read(path).expect("Can't read the recording");
+        let replay = native::replay::Replay::new(entries).expect("The recording contains no game state");
+        native::gui::BevyGUI::replay(replay);
+        return
+    }
+    let physics = args.physics.as_ref().map(|path| {
+        let json = std::fs::read(path).expect("Can't read the physics file");
+        serde_json::from_slice::<simulation::PhysicsParams>(&json).expect("Invalid physics file")
+    });
+    if let Some(path) = &args.resimulate {
+        use tracing::info;
+
+        let entries = record::read(path).expect("Can't read the recording");
+        let mut resim = native::resimulation::Resimulation::new(&entries).expect("The recording contains no game state");
+        if let Some(physics) = physics {
+            resim.set_physics_params(physics);
+        }
+        // The bots replace the recorded commands of their team
+        for (team, name) in game_state::Team::all().into_iter().zip(args.bots.iter()) {
+            if let Some(name) = name {
+                resim.remove_commands(team);
+                resim.gc.set_strategy(team, Some(strategy::by_name(name).expect("Unknown built-in strategy")));
+            }
+        }
+        resim.gc.set_extended_state(args.extended_state);
+        if let Some(path) = &args.record {
+            resim.gc.start_recording(path).expect("Can't create the recording file");
+        }
+        resim.run();
+        let teams = resim.gc.get_game_state().referee.teams;
+        info!("Re-simulation finished. Score : {} - {}", teams.blue.score, teams.green.score);
+        return
+    }
+    let mut gc = native::gc::GC::new("".to_string(), "".to_string(), "".to_string(), "".to_string(), false);
+    if let Some(physics) = physics {
+        gc.set_physics_params(physics);
+    }
+    gc.set_publish_rate(args.publish_rate);
+    gc.set_extended_state(args.extended_state);
+    gc.set_decision_rate(args.decision_rate);
+    for (team, name) in game_state::Team::all().into_iter().zip(args.bots.iter()) {
+        if let Some(name) = name {
+            gc.set_strategy(team, Some(strategy::by_name(name).expect("Unknown built-in strategy")));
+        }
+    }
+    if let Some(steps) = args.lockstep {
+        gc.set_lockstep(Some(native::control::LockstepConfig {
+            steps,
+            timeout: args.lockstep_timeout
+        }));
+    }
+
+    if let Some(path) = &args.record {
+        gc.start_recording(path).expect("Can't create the recording file");
+    }
+
+    if let Some(first) = args.shootout {
+        gc.start_shootout(first);
+    }
+    let mut scenarios: Vec<_> = args.scenarios.iter()
+        .map(|path| native::scenario::Scenario::load(path).expect("Can't read the scenario"))
+        .collect();
+    if args.headless {
+        use std::time::Instant;
+        use tracing::info;
+
+        if !scenarios.is_empty() {
+            let total = scenarios.len();
+            let mut failed = Vec::new();
+            for scenario in scenarios {
+                let name = scenario.name.clone();
+                gc.start_scenario(scenario);
+                let start = (Instant::now(), gc.simu.t);
+                while gc.scenario_result().is_none() {
+                    gc.step();
+                    // In lockstep mode, the controllers set the pace
+                    if args.lockstep.is_none() {
+                        wait_real_time(&gc, start);
+                    }
+                }
+                if !gc.scenario_result().unwrap().passed {
+                    failed.push(name);
+                }
+            }
+            info!("{}/{} scenarios passed", total - failed.len(), total);
+            if !failed.is_empty() {
+                info!("Failed : {}", failed.join(", "));
+                std::process::exit(1);
+            }
+            return
+        }
+
+        let start = (Instant::now(), gc.simu.t);
+        loop {
+            gc.step();
+            // In lockstep mode, the controllers set the pace
+            if args.lockstep.is_none() {
+                wait_real_time(&gc, start);
+            }
+        }
+    }
+    if !scenarios.is_empty() {
+        if scenarios.len() > 1 {
+            tracing::warn!("Only the first scenario is run with the GUI");
+        }
+        gc.start_scenario(scenarios.swap_remove(0));
+    }
 
     native::gui::BevyGUI::run(gc);
 }
 
+/// Sleeps until the real time since `start` (instant, frame) catches up with the simulation
+#[cfg(all(feature = "native", not(target_arch = "wasm32")))]
+fn wait_real_time(gc: &GC, start: (std::time::Instant, usize)) {
+    let t = std::time::Duration::from_secs_f64((gc.simu.t - start.1) as f64 * constants::DT);
+    if let Some(d) = t.checked_sub(start.0.elapsed()) {
+        std::thread::sleep(d);
+    }
+}
+
 #[cfg(all(feature = "http_client_gc", target_arch = "wasm32"))]
 fn main() {
     use tracing::{debug, info};
@@ -59,27 +183,21 @@ fn main() {
 #[cfg(all(feature = "http_server", not(target_arch = "wasm32")))]
 #[tokio::main]
 async fn main() {
-    http::default::server::main().await
+    rsk_simulation::http::default::server::main().await
 }
 
-#[cfg(all(feature = "http_client", target_arch = "wasm32"))]
-pub type Control = http::default::client::Control;
-#[cfg(all(feature = "http_client", target_arch = "wasm32"))]
-pub type GC = native::gc::GC;
 #[cfg(all(feature = "http_client", target_arch = "wasm32"))]
 fn main() {
-    http::default::client::main()
+    rsk_simulation::http::default::client::main()
 }
 
-#[cfg(all(feature = "alternative_http_client", target_arch = "wasm32"))]
-pub type GC = http::alternative::client::GC;
 #[cfg(all(feature = "alternative_http_client", target_arch = "wasm32"))]
 fn main() {
-    http::alternative::client::main()
+    rsk_simulation::http::alternative::client::main()
 }
 
 #[cfg(all(feature = "alternative_http_server", not(target_arch = "wasm32")))]
 #[tokio::main]
 async fn main() {
-    http::alternative::server::main().await
+    rsk_simulation::http::alternative::server::main().await
 }
\ No newline at end of file
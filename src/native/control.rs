@@ -1,20 +1,83 @@
 //! A control implementation that can only run nativelly and uses a tokio runtime internally
 //! Tries to be as compatible as possible with the official api of the python game controller
 
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex as StdMutex};
+use std::time::Duration;
 use tokio::{runtime::Runtime, sync::Mutex};
 use tracing::warn;
 use zeromq::{PubSocket, RepSocket, Socket, SocketSend, SocketRecv};
 
 use serde_json::Value;
 
-use crate::game_state::{GameState, Robot, RobotTasks};
+use crate::debug::{Layer, Primitive};
+use crate::game_state::{GameState, Geometry, Robot, RobotTasks, Team};
 
 use crate::control::CtrlRes;
+#[cfg(feature = "record")]
+use crate::strategy::Command;
+
+/// Lockstep mode : the simulation waits for the controllers after each published frame
+#[derive(Clone, Copy, Debug)]
+pub struct LockstepConfig {
+    /// Number of DT steps between two frames
+    pub steps: usize,
+    /// Maximum time to wait for the controllers
+    pub timeout: Duration,
+}
+
+/// [blue, green]
+#[derive(Default)]
+struct LockstepTeams {
+    /// Teams that sent the "register" command
+    registered: [bool; 2],
+    /// Teams that acknowledged the last published frame
+    answered: [bool; 2],
+    /// Last published frame, the only one that can be acknowledged
+    frame: Option<usize>,
+}
+
+#[derive(Default)]
+struct Lockstep {
+    teams: StdMutex<LockstepTeams>,
+    cond: Condvar,
+}
+impl Lockstep {
+    fn register(&self, team: usize, registered: bool) {
+        self.teams.lock().unwrap().registered[team] = registered;
+        self.cond.notify_all();
+    }
+    /// Returns false if `frame` is not the frame waited for, for example an ack that arrives after the timeout
+    fn answer(&self, team: usize, frame: usize) -> bool {
+        let mut teams = self.teams.lock().unwrap();
+        if teams.frame != Some(frame) {
+            return false
+        }
+        teams.answered[team] = true;
+        self.cond.notify_all();
+        true
+    }
+}
+
+/// The frame acknowledged by `cmd` if it is an ["ack", frame] command.
+/// The other two elements commands, like ["kick", power], are for the robots
+fn ack_frame(cmd: &[Value]) -> Option<u64> {
+    match cmd {
+        [Value::String(c), Value::Number(frame)] if c == "ack" => frame.as_u64(),
+        _ => None
+    }
+}
 
 pub struct Control {
     state_socket: PubSocket,
-    rt: Runtime
+    rt: Runtime,
+    lockstep: Arc<Lockstep>,
+    /// Maximum time to wait for the controllers. None if lockstep mode is disabled
+    lockstep_timeout: Option<Duration>,
+    /// Commands received since the last call to `take_commands`, with their key
+    #[cfg(feature = "record")]
+    received: Arc<StdMutex<Vec<(String, Command)>>>,
+    /// Debug layers received since the last call to `take_debug_layers`
+    debug_layers: Arc<StdMutex<Vec<Layer>>>,
 }
 impl Control {
     pub fn new(keys: [String; 2], tasks: Arc<Mutex<[RobotTasks; 4]>>) -> Self {
@@ -27,6 +90,15 @@ impl Control {
         
         rt.block_on(state_socket.bind("tcp://127.0.0.1:7557")).unwrap();
         rt.block_on(ctrl_socket.bind("tcp://127.0.0.1:7558")).unwrap();
+
+        let lockstep = Arc::new(Lockstep::default());
+        let lockstep_ref = lockstep.clone();
+        #[cfg(feature = "record")]
+        let received = Arc::new(StdMutex::new(Vec::new()));
+        #[cfg(feature = "record")]
+        let received_ref = received.clone();
+        let debug_layers = Arc::new(StdMutex::new(Vec::new()));
+        let debug_layers_ref = debug_layers.clone();
         
         rt.spawn(async move {
             loop {
@@ -46,6 +118,43 @@ impl Control {
                         if keys[num] != key {
                             dbg!(&key);
                             res = CtrlRes::BadKey(team);
+                        } else if let [Value::String(c)] = cmd.as_slice() {
+                            // Lockstep commands
+                            res = match c.as_str() {
+                                "register" => {
+                                    lockstep_ref.register(num, true);
+                                    CtrlRes::Ok
+                                },
+                                "unregister" => {
+                                    lockstep_ref.register(num, false);
+                                    CtrlRes::Ok
+                                },
+                                _ => CtrlRes::UnknownCommand
+                            };
+                        } else if let Some(frame) = ack_frame(&cmd) {
+                            // The commands don't count as an answer : a controller usually sends several of them per frame,
+                            // only the ack tells that it's done
+                            if !lockstep_ref.answer(num, frame as usize) {
+                                warn!("Ignored ack of {} for frame {}, not the current frame", team, frame);
+                            }
+                            res = CtrlRes::Ok;
+                        } else if let [Value::String(c), Value::String(namespace), primitives] = cmd.as_slice() {
+                            // Debug drawings
+                            res = match (c.as_str(), serde_json::from_value::<Vec<Primitive>>(primitives.clone())) {
+                                ("debug", Ok(primitives)) => {
+                                    debug_layers_ref.lock().unwrap().push(Layer {
+                                        team: if num == 0 {Team::Blue} else {Team::Green},
+                                        namespace: namespace.clone(),
+                                        primitives
+                                    });
+                                    CtrlRes::Ok
+                                },
+                                ("debug", Err(e)) => {
+                                    warn!("Invalid debug primitives : {}", e);
+                                    CtrlRes::UnknownCommand
+                                },
+                                _ => CtrlRes::UnknownCommand
+                            };
                         } else {
                             // TODO: Add option to disable control for one team
                             if let Some(r) = match (team.as_str(), number) {
@@ -57,9 +166,9 @@ impl Control {
                             } {
                                 let mut tasks = tasks.lock().await;
                                 let mut preempted = false;
-                                if let Some((r, _, _)) = tasks[r as usize].penalty {
+                                if let Some(reason) = tasks[r as usize].preemption() {
                                     preempted = true;
-                                    res = CtrlRes::Preempted(team, number, r.to_string());
+                                    res = CtrlRes::Preempted(team, number, reason.to_string());
                                 }
                                 if !preempted {
                                     match cmd.len() {
@@ -71,8 +180,13 @@ impl Control {
                                                         cmd[2].as_f64().unwrap_or(0.) as f32,
                                                         cmd[3].as_f64().unwrap_or(0.) as f32
                                                     );
+                                                    // Logged while the tasks are locked, so that the game controller sees it at the frame where it is applied
+                                                    #[cfg(feature = "record")]
+                                                    received_ref.lock().unwrap().push((key.clone(), Command::Control(r, tasks[r as usize].control)));
                                                     res = CtrlRes::Ok;
                                                 }
+                                                // The simulated robots have no leds
+                                                "leds" => res = CtrlRes::Ok,
                                                 _ => res = CtrlRes::UnknownCommand
                                             },
                                             _ => res = CtrlRes::UnknownCommand
@@ -80,7 +194,10 @@ impl Control {
                                         2 => match &cmd[0] {
                                             Value::String(c) => match c.as_str() {
                                                 "kick" => {
-                                                    tasks[r as usize].kick = Some(cmd[1].as_f64().unwrap_or(0.) as f32);
+                                                    let power = cmd[1].as_f64().unwrap_or(0.) as f32;
+                                                    tasks[r as usize].kick = Some(power);
+                                                    #[cfg(feature = "record")]
+                                                    received_ref.lock().unwrap().push((key.clone(), Command::Kick(r, power)));
                                                     res = CtrlRes::Ok;
                                                 }
                                                 _ => res = CtrlRes::UnknownCommand
@@ -95,6 +212,7 @@ impl Control {
                             }
                         }
                     },
+                    "geometry" => res = CtrlRes::Geometry(Geometry::default()),
                     "ball" => todo!(),
                     _ => {dbg!(key, team, number, cmd);}
                 }
@@ -103,12 +221,60 @@ impl Control {
         });
         Self {
             rt,
-            state_socket
+            state_socket,
+            lockstep,
+            lockstep_timeout: None,
+            #[cfg(feature = "record")]
+            received,
+            debug_layers
         }
     }
-    /// Send new game state to client
-    pub fn publish(&mut self, gs: GameState) {
-        let json = serde_json::to_vec(&gs).unwrap();
-        self.rt.block_on(self.state_socket.send(json.into())).unwrap();
+    /// Debug layers received since the last call, in their order of arrival
+    pub fn take_debug_layers(&self) -> Vec<Layer> {
+        std::mem::take(&mut *self.debug_layers.lock().unwrap())
+    }
+    /// Commands received since the last call, with their key
+    #[cfg(feature = "record")]
+    pub fn take_commands(&self) -> Vec<(String, Command)> {
+        std::mem::take(&mut *self.received.lock().unwrap())
+    }
+    /// Enable lockstep mode with the given timeout or disable it with None
+    pub fn set_lockstep(&mut self, timeout: Option<Duration>) {
+        self.lockstep_timeout = timeout;
     }
-}
\ No newline at end of file
+    /// Send new game state of frame `t` to client. In lockstep mode, the frame is added to the game state
+    /// and all the registered controllers must acknowledge it with `["ack", t]`
+    pub fn publish(&mut self, gs: GameState, t: usize) {
+        if let Some(timeout) = self.lockstep_timeout {
+            {
+                let mut teams = self.lockstep.teams.lock().unwrap();
+                teams.answered = [false; 2];
+                teams.frame = Some(t);
+            }
+            self.rt.block_on(self.state_socket.send(gs.to_json_with_frame(t).into())).unwrap();
+            let teams = self.lockstep.teams.lock().unwrap();
+            let (_, res) = self.lockstep.cond.wait_timeout_while(teams, timeout, |teams| {
+                teams.registered.iter().zip(teams.answered.iter()).any(|(r, a)| *r && !*a)
+            }).unwrap();
+            if res.timed_out() {
+                warn!("Lockstep timeout");
+            }
+        } else {
+            self.rt.block_on(self.state_socket.send(gs.to_json().into())).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn only_ack_commands_are_acknowledgements() {
+        assert_eq!(ack_frame(&[json!("ack"), json!(42)]), Some(42));
+        assert_eq!(ack_frame(&[json!("kick"), json!(42)]), None);
+        assert_eq!(ack_frame(&[json!("kick"), json!(0.5)]), None);
+        assert_eq!(ack_frame(&[json!("ack")]), None);
+    }
+}
@@ -1,6 +1,7 @@
 //! The main game controller implementation. Runs both nativelly and on wasm
 
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::rc::Rc;
 #[cfg(not(target_arch = "wasm32"))]
 use std::sync::Arc;
@@ -8,17 +9,29 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::constants::simu::*;
+use crate::debug::{DebugDrawings, Layer};
 use crate::game_state::{
-    GameState, Markers, Pose, RefereeTeam, RefereeTeamRobot, RefereeTeamRobots,
-    RefereeTeams, Robot, RobotTasks, Referee as GSReferee
+    Extended, GameState, Markers, Pose, RefereeTeam, RefereeTeamRobot, RefereeTeamRobots,
+    RefereeTeams, Robot, RobotTasks, Referee as GSReferee, Team, Velocities, Velocity
 };
-use crate::simulation::Simulation;
-use crate::referee::Referee;
+use crate::simulation::{PhysicsParams, Simulation};
+use crate::strategy::{Command, Strategy};
+use crate::referee::{Referee, RefereeEvent, PENALTY_REASONS};
+#[cfg(feature = "record")]
+use crate::referee::SHOOTOUT;
 use rapier2d_f64::prelude::*;
-use tracing::info;
+use tracing::{info, warn};
 
 #[cfg(feature = "control")]
 use crate::Control;
+#[cfg(feature = "native_control")]
+use crate::native::control::LockstepConfig;
+#[cfg(feature = "record")]
+use crate::record::{RecordConfig, RecordEntry, Recorder};
+#[cfg(feature = "json")]
+use crate::native::scenario::{Scenario, ScenarioResult, ScenarioRunner};
+#[cfg(feature = "record")]
+use crate::native::scenario::SCRIPTED;
 
 #[cfg(not(target_arch = "wasm32"))]
 type TasksType = Arc<Mutex<[RobotTasks; 4]>>;
@@ -26,13 +39,90 @@ type TasksType = Arc<Mutex<[RobotTasks; 4]>>;
 #[cfg(target_arch = "wasm32")]
 type TasksType = Rc<RefCell<[RobotTasks; 4]>>;
 
+/// Ball or robot, for the recorded interventions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Entity {
+    Ball,
+    Robot(Robot),
+}
+
+/// Actions on the game controller from outside of its step : GUI, scripts and scenarios.
+/// They are recorded (see `record`) and applied at the same frame by the re-simulation
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Intervention {
+    /// Position in meters, see `GC::teleport_entity`
+    Teleport {
+        entity: Entity,
+        position: Point<f64>,
+        orientation: Option<f64>,
+    },
+    /// In m/s
+    Velocity {
+        entity: Entity,
+        velocity: Vector<f64>,
+    },
+    /// Ball and robots back to their initial positions
+    Reset,
+    /// See `GC::reset_match`
+    ResetMatch,
+    Kick(Robot, f64),
+    Physics(PhysicsParams),
+    Start,
+    Pause,
+    Resume,
+    Halftime,
+    CancelGoal,
+    /// (robot, reason)
+    Penalize(Robot, String),
+    Unpenalize(Robot),
+    /// Penalty shootout, with the team that kicks first
+    Shootout(Team),
+    #[cfg(feature = "json")]
+    Scenario(Scenario),
+}
+
+/// Converts a rate in Hz to a number of frames
+fn publish_period(rate: usize) -> usize {
+    ((1. / DT) / rate.max(1) as f64).round().max(1.) as usize
+}
+
 /// Game controller
 pub struct GC {
+    /// None if the game controller runs without sockets
     #[cfg(feature = "control")]
-    control: Control,
+    control: Option<Control>,
     pub simu: Simulation,
     /// It’s None if game has not started
     pub referee: Referee,
+    /// Number of frames between two published game states
+    publish_period: usize,
+    /// Publish period set by the publish rate, restored when the lockstep mode is disabled
+    rate_period: usize,
+    lockstep: bool,
+    /// Add velocities and timestamps to the game state
+    extended_state: bool,
+    /// In-process strategies [blue, green]
+    strategies: [Option<Box<dyn Strategy>>; 2],
+    /// Number of frames between two updates of the strategies
+    decision_period: usize,
+    /// Commands (control, kick) waiting for the command latency of the physics parameters
+    delayed_commands: VecDeque<[((f32, f32, f32), Option<f32>); 4]>,
+    /// Debug drawings of the strategies, shown by the GUI
+    pub debug: DebugDrawings,
+    /// Referee events of the last step
+    pub last_events: Vec<RefereeEvent>,
+    #[cfg(feature = "json")]
+    scenario: Option<ScenarioRunner>,
+    #[cfg(feature = "record")]
+    recorder: Option<Recorder>,
+    /// True during the step and the interventions : the game controller acts by itself, the re-simulation repeats it
+    #[cfg(feature = "record")]
+    internal: bool,
+    /// Last recorded control of the robots driven by hand (see `RecordEntry::Drive`)
+    #[cfg(feature = "record")]
+    recorded_drives: [Option<(f32, f32, f32)>; 4],
 }
 impl GC {
     pub fn new(
@@ -44,35 +134,318 @@ impl GC {
         #[cfg(feature = "http_client")]
         session_id: &str
     ) -> Self {
-        let simu = Simulation::new();
-        let referee = Referee::new(blue_team_name, green_team_name, blue_team_key.clone(), green_team_key.clone(), blue_team_positive);
+        let gc = Self::headless(blue_team_name, green_team_name, blue_team_key.clone(), green_team_key.clone(), blue_team_positive);
         Self {
             #[cfg(feature = "control")]
-            control: Control::new(
+            control: Some(Control::new(
                 [blue_team_key, green_team_key],
-                referee.tasks.clone(),
+                gc.referee.tasks.clone(),
                 #[cfg(feature = "http_client")]
                 session_id
-            ),
-            simu,
-            referee,
+            )),
+            ..gc
+        }
+    }
+    /// Game controller without the control sockets. Many of them can run at the same time
+    pub fn headless(
+        blue_team_name: String,
+        green_team_name: String,
+        blue_team_key: String,
+        green_team_key: String,
+        blue_team_positive: bool
+    ) -> Self {
+        Self {
+            #[cfg(feature = "control")]
+            control: None,
+            simu: Simulation::new(),
+            referee: Referee::new(blue_team_name, green_team_name, blue_team_key, green_team_key, blue_team_positive),
+            publish_period: publish_period(DEFAULT_PUBLISH_RATE),
+            rate_period: publish_period(DEFAULT_PUBLISH_RATE),
+            lockstep: false,
+            extended_state: false,
+            strategies: [None, None],
+            decision_period: publish_period(DEFAULT_DECISION_RATE),
+            delayed_commands: VecDeque::new(),
+            debug: DebugDrawings::default(),
+            last_events: Vec::new(),
+            #[cfg(feature = "json")]
+            scenario: None,
+            #[cfg(feature = "record")]
+            recorder: None,
+            #[cfg(feature = "record")]
+            internal: false,
+            #[cfg(feature = "record")]
+            recorded_drives: [None; 4],
         }
     }
+    /// Let a strategy control a team, or give the control back to the sockets with None
+    pub fn set_strategy(&mut self, team: Team, strategy: Option<Box<dyn Strategy>>) {
+        self.strategies[team as usize] = strategy;
+    }
+    /// Set the rate at which the strategies are updated (in Hz)
+    pub fn set_decision_rate(&mut self, rate: usize) {
+        self.decision_period = publish_period(rate);
+    }
+    /// Enable the lockstep mode : after each published frame, the simulation waits for all the registered controllers (or the timeout) and then advances `steps` frames
+    #[cfg(feature = "native_control")]
+    pub fn set_lockstep(&mut self, lockstep: Option<LockstepConfig>) {
+        match lockstep {
+            Some(config) => {
+                self.publish_period = config.steps.max(1);
+                self.lockstep = true;
+                if let Some(control) = &mut self.control {
+                    control.set_lockstep(Some(config.timeout));
+                }
+            },
+            None => {
+                self.publish_period = self.rate_period;
+                self.lockstep = false;
+                if let Some(control) = &mut self.control {
+                    control.set_lockstep(None);
+                }
+            }
+        }
+    }
+    /// Add the extended section (velocities, frame and timestamp) to the game state
+    pub fn set_extended_state(&mut self, extended: bool) {
+        self.extended_state = extended;
+    }
+    /// Applies new physical parameters (see `PhysicsParams`) without rebuilding the simulation
+    pub fn set_physics_params(&mut self, params: PhysicsParams) {
+        self.recorded(Some(Intervention::Physics(params.clone())), |gc| {
+            gc.delayed_commands.clear();
+            gc.simu.set_params(params);
+        })
+    }
+    /// Records the match in `path` (see `record`), the game states are recorded at the publish rate
+    #[cfg(feature = "record")]
+    pub fn start_recording(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let mut recorder = Recorder::create(path)?;
+        let gs = self.get_game_state();
+        recorder.write(&RecordEntry::Config(RecordConfig {
+            blue_team_name: gs.referee.teams.blue.name.clone(),
+            green_team_name: gs.referee.teams.green.name.clone(),
+            blue_team_positive: gs.referee.teams.blue.x_positive,
+            physics: self.simu.params.clone(),
+            frame_period: self.publish_period
+        }));
+        recorder.write(&RecordEntry::Frame { t: self.simu.t, state: gs });
+        for layer in self.debug.layers.iter() {
+            recorder.write(&RecordEntry::Debug { t: self.simu.t, layer: layer.clone() });
+        }
+        self.recorder = Some(recorder);
+        self.recorded_drives = [None; 4];
+        Ok(())
+    }
+    #[cfg(feature = "record")]
+    pub fn stop_recording(&mut self) {
+        self.recorder = None;
+    }
+    /// Records the intervention if it comes from outside of the game controller, then applies it.
+    /// The actions nested in `apply` are not recorded
+    pub(crate) fn recorded<T>(&mut self, intervention: Option<Intervention>, apply: impl FnOnce(&mut Self) -> T) -> T {
+        #[cfg(feature = "record")]
+        if !self.internal {
+            if let (Some(recorder), Some(intervention)) = (&mut self.recorder, intervention) {
+                recorder.write(&RecordEntry::Intervention { t: self.simu.t, intervention });
+            }
+            self.internal = true;
+            let result = apply(self);
+            self.internal = false;
+            return result
+        }
+        #[cfg(not(feature = "record"))]
+        let _ = intervention;
+        apply(self)
+    }
+    /// Applies an intervention, for example from the GUI or a recording. Returns false if there was nothing to do (no goal to cancel)
+    pub fn intervene(&mut self, intervention: Intervention) -> bool {
+        match intervention {
+            Intervention::Teleport { entity, position, orientation } => self.teleport_entity(self.entity_handle(entity), position, orientation),
+            Intervention::Velocity { entity, velocity } => self.set_velocity(self.entity_handle(entity), velocity),
+            Intervention::Reset => self.reset(),
+            Intervention::ResetMatch => self.reset_match(),
+            Intervention::Kick(r, f) => self.kick(r, f),
+            Intervention::Physics(params) => self.set_physics_params(params),
+            Intervention::Penalize(r, reason) => {
+                // The recorded reasons are mapped back to the static ones
+                let reason = PENALTY_REASONS.into_iter().find(|&known| known == reason).unwrap_or_else(|| {
+                    warn!("Unknown penalty reason {:?}, replaced by {:?}", reason, PENALTY_REASONS[0]);
+                    PENALTY_REASONS[0]
+                });
+                self.penalize(r, reason)
+            },
+            Intervention::Shootout(team) => self.start_shootout(team),
+            #[cfg(feature = "json")]
+            Intervention::Scenario(scenario) => self.start_scenario(scenario),
+            Intervention::Start | Intervention::Pause | Intervention::Resume | Intervention::Halftime
+                | Intervention::CancelGoal | Intervention::Unpenalize(_) => {
+                let t = self.simu.t;
+                return self.recorded(Some(intervention.clone()), |gc| {
+                    match intervention {
+                        Intervention::Start => gc.referee.start(t),
+                        Intervention::Pause => gc.referee.pause(t),
+                        Intervention::Resume => gc.referee.resume(t),
+                        Intervention::Halftime => gc.referee.halftime(),
+                        Intervention::CancelGoal => return gc.referee.cancel_goal(),
+                        Intervention::Unpenalize(r) => gc.referee.unpenalize(r),
+                        _ => unreachable!()
+                    }
+                    true
+                })
+            }
+        }
+        true
+    }
+    /// Replaces a layer of debug drawings (see `debug`), for example from an in-process strategy
+    pub fn draw_debug(&mut self, layer: Layer) {
+        #[cfg(feature = "record")]
+        if let Some(recorder) = &mut self.recorder {
+            recorder.write(&RecordEntry::Debug { t: self.simu.t, layer: layer.clone() });
+        }
+        self.debug.set(layer);
+    }
+    /// Restarts the match in the initial situation of a scenario (see `native::scenario`)
+    #[cfg(feature = "json")]
+    pub fn start_scenario(&mut self, scenario: Scenario) {
+        self.recorded(Some(Intervention::Scenario(scenario.clone())), |gc| {
+            let mut runner = ScenarioRunner::new(scenario);
+            runner.setup(gc);
+            gc.scenario = Some(runner);
+        })
+    }
+    /// None while the scenario is running or without scenario
+    #[cfg(feature = "json")]
+    pub fn scenario_result(&self) -> Option<&ScenarioResult> {
+        self.scenario.as_ref().and_then(|s| s.result.as_ref())
+    }
+    /// Set the rate at which the game state is sent to the clients (in Hz). In lockstep mode, it's used once the mode is disabled
+    pub fn set_publish_rate(&mut self, rate: usize) {
+        self.rate_period = publish_period(rate);
+        if !self.lockstep {
+            self.publish_period = self.rate_period;
+        }
+    }
+    /// Game state streamed by the server when another client runs the session (see `http::default::server`).
+    /// The local simulation is then stopped
+    pub fn spectated_state(&self) -> Option<GameState> {
+        #[cfg(feature = "http_client")]
+        if let Some(control) = &self.control {
+            return control.spectated()
+        }
+        None
+    }
     pub fn step(&mut self) {
+        #[cfg(feature = "http_client")]
+        if self.spectated_state().is_some() {
+            return
+        }
+        #[cfg(feature = "record")]
+        {
+            self.internal = true;
+        }
+        // Taken out during the step because it drives the game controller
+        #[cfg(feature = "json")]
+        let mut scenario = self.scenario.take();
+        #[cfg(feature = "json")]
+        if let Some(scenario) = &mut scenario {
+            scenario.drive(self);
+        }
+        #[cfg(feature = "native_control")]
+        if let Some(control) = &self.control {
+            for layer in control.take_debug_layers() {
+                self.draw_debug(layer);
+            }
+        }
+        if self.simu.t % self.decision_period == 0 && self.strategies.iter().any(Option::is_some) {
+            self.update_strategies();
+        }
+        // Like with the official referee, the robots don't move while the game is not running
+        let running = self.referee.is_running();
         #[cfg(not(target_arch = "wasm32"))]
         let mut tasks = self.referee.tasks.blocking_lock();
         #[cfg(target_arch = "wasm32")]
         let mut tasks = self.referee.tasks.borrow_mut();
+        #[cfg(all(feature = "record", feature = "native_control"))]
+        if let Some(control) = &self.control {
+            // Taken while the tasks are locked : these are exactly the commands applied at this frame
+            let commands = control.take_commands();
+            if let Some(recorder) = &mut self.recorder {
+                for (key, command) in commands {
+                    recorder.write(&RecordEntry::Command { t: self.simu.t, key, command });
+                }
+            }
+        }
+        #[cfg(feature = "record")]
+        if let Some(recorder) = &mut self.recorder {
+            for r in Robot::all() {
+                let task = &tasks[r as usize];
+                // The robots driven by the referee and the scenarios move the same way in the re-simulation
+                let control = task.driven.filter(|reason| *reason != SHOOTOUT && *reason != SCRIPTED).map(|_| task.control);
+                let kick = control.and(task.kick);
+                if control != self.recorded_drives[r as usize] || kick.is_some() {
+                    recorder.write(&RecordEntry::Drive { t: self.simu.t, robot: r, control, kick });
+                    self.recorded_drives[r as usize] = control;
+                }
+            }
+        }
+        self.delayed_commands.push_back(std::array::from_fn(|i| {
+            let kick = tasks[i].kick.take();
+            if running {
+                (tasks[i].control, kick)
+            } else {
+                ((0., 0., 0.), None)
+            }
+        }));
+        drop(tasks);
+        // With a latency of n frames, the commands are applied n frames after being received
+        if self.delayed_commands.len() > self.simu.params.command_latency {
+            let commands = self.delayed_commands.pop_front().unwrap();
+            self.apply_commands(commands);
+        }
+        self.simu.step();
+        #[cfg(feature = "referee")]
+        self.referee_step();
+        #[cfg(feature = "record")]
+        if self.recorder.is_some() {
+            let frame = (self.simu.t % self.publish_period == 0).then(|| self.get_game_state());
+            let recorder = self.recorder.as_mut().unwrap();
+            for event in self.referee.events.iter() {
+                recorder.write(&RecordEntry::Event { t: self.simu.t, event: event.clone() });
+            }
+            if let Some(gs) = frame {
+                recorder.write(&RecordEntry::Frame { t: self.simu.t, state: gs });
+            }
+        }
+        self.last_events = std::mem::take(&mut self.referee.events);
+        #[cfg(feature = "json")]
+        if let Some(mut scenario) = scenario {
+            scenario.check(self);
+            self.scenario = Some(scenario);
+        }
+        #[cfg(feature = "control")]
+        if self.control.is_some() && self.simu.t % self.publish_period == 0 {
+            let gs = self.get_game_state();
+            self.control.as_mut().unwrap().publish(gs, self.simu.t);
+        }
+        #[cfg(feature = "record")]
+        {
+            self.internal = false;
+        }
+    }
+    /// Sets the speeds of the robots and kicks
+    fn apply_commands(&mut self, commands: [((f32, f32, f32), Option<f32>); 4]) {
+        let max_speed = self.simu.params.robot_speed*MULTIPLIER;
         for robot in Robot::all() {
-            let (x, y, r) = tasks[robot as usize].control;
+            let ((x, y, r), kick) = commands[robot as usize];
             let x = x as f64*MULTIPLIER;
             let y = y as f64*MULTIPLIER;
 
             let handle = self.get_robot_handle(robot);
             let body = &mut self.simu.bodies[handle];
             let mut speed = vector![x, y].norm();
-            if speed > ROBOT_SPEED {
-                speed = ROBOT_SPEED;
+            if speed > max_speed {
+                speed = max_speed;
             }
             let angle = y.atan2(x) + body.rotation().angle();
             let x = angle.cos();
@@ -84,18 +457,36 @@ impl GC {
             body.set_linvel(linvel, true);
             body.set_angvel(angvel, true);
             
-            if let Some(f) = tasks[robot as usize].kick {
+            if let Some(f) = kick {
                 info!("{:?} : {}", robot, f);
                 self.simu.kick(robot, f as f64);
-                tasks[robot as usize].kick = None;
             }
         }
-        drop(tasks);
-        self.simu.step();
-        #[cfg(feature = "referee")]
-        self.referee_step();
-        #[cfg(feature = "control")]
-        self.control.publish(self.get_game_state());
+    }
+    fn update_strategies(&mut self) {
+        let gs = self.get_game_state();
+        let mut tasks = self.referee.lock_tasks_mut();
+        for (team, strategy) in Team::all().into_iter().zip(self.strategies.iter_mut()) {
+            let strategy = match strategy {
+                Some(s) => s,
+                None => continue
+            };
+            for cmd in strategy.update(&gs, team) {
+                let (Command::Control(r, _) | Command::Kick(r, _)) = cmd;
+                // Like in the control socket, penalized and driven robots are preempted
+                if r.team() != team || tasks[r as usize].preemption().is_some() {
+                    continue
+                }
+                match cmd {
+                    Command::Control(r, control) => tasks[r as usize].control = control,
+                    Command::Kick(r, f) => tasks[r as usize].kick = Some(f)
+                }
+                #[cfg(feature = "record")]
+                if let Some(recorder) = &mut self.recorder {
+                    recorder.write(&RecordEntry::Command { t: self.simu.t, key: String::new(), command: cmd });
+                }
+            }
+        }
     }
     pub fn get_game_state(&self) -> GameState {
         let robots = Robot::all().map(|r| &self.simu.bodies[self.get_robot_handle(r)]);
@@ -133,7 +524,28 @@ impl GC {
                     orientation: robots[Robot::Green2 as usize].rotation().angle(),
                 },
             },
-            referee: self.referee.get_gs_referee(self.simu.t)
+            referee: self.referee.get_gs_referee(self.simu.t),
+            extended: self.extended_state.then(|| self.get_extended())
+        }
+    }
+    fn get_extended(&self) -> Extended {
+        let velocity = |handle: RigidBodyHandle| {
+            let body = &self.simu.bodies[handle];
+            Velocity {
+                linear: body.linvel()/MULTIPLIER,
+                angular: body.angvel()
+            }
+        };
+        Extended {
+            t: self.simu.t,
+            timestamp: self.simu.t as f64 * DT,
+            ball_velocity: self.simu.bodies[self.simu.ball].linvel()/MULTIPLIER,
+            velocities: Velocities {
+                blue1: velocity(self.get_robot_handle(Robot::Blue1)),
+                blue2: velocity(self.get_robot_handle(Robot::Blue2)),
+                green1: velocity(self.get_robot_handle(Robot::Green1)),
+                green2: velocity(self.get_robot_handle(Robot::Green2)),
+            }
         }
     }
     /// pos in real coordinates
@@ -142,7 +554,26 @@ impl GC {
     }
     /// pos in real coordinates
     pub fn teleport_entity(&mut self, entity: RigidBodyHandle, pos: Point<f64>, r: Option<f64>) {
-        self.simu.teleport_entity(entity, pos*MULTIPLIER, r)
+        let intervention = self.entity(entity).map(|entity| Intervention::Teleport { entity, position: pos, orientation: r });
+        self.recorded(intervention, |gc| gc.simu.teleport_entity(entity, pos*MULTIPLIER, r))
+    }
+    /// linvel in m/s
+    pub fn set_velocity(&mut self, entity: RigidBodyHandle, linvel: Vector<f64>) {
+        let intervention = self.entity(entity).map(|entity| Intervention::Velocity { entity, velocity: linvel });
+        self.recorded(intervention, |gc| gc.simu.bodies[entity].set_linvel(linvel*MULTIPLIER, true))
+    }
+    /// None if the body is neither the ball nor a robot
+    fn entity(&self, handle: RigidBodyHandle) -> Option<Entity> {
+        if handle == self.get_ball_handle() {
+            return Some(Entity::Ball)
+        }
+        Robot::all().into_iter().find(|r| self.get_robot_handle(*r) == handle).map(Entity::Robot)
+    }
+    fn entity_handle(&self, entity: Entity) -> RigidBodyHandle {
+        match entity {
+            Entity::Ball => self.get_ball_handle(),
+            Entity::Robot(r) => self.get_robot_handle(r)
+        }
     }
     pub fn get_ball_handle(&self) -> RigidBodyHandle {
         self.simu.get_ball_handle()
@@ -151,10 +582,18 @@ impl GC {
         self.simu.get_robot_handle(id)
     }
     pub fn reset(&mut self) {
-        self.simu.reset()
+        self.recorded(Some(Intervention::Reset), |gc| gc.simu.reset())
+    }
+    /// Starts a new match : resets the positions, the scores and the penalties
+    pub fn reset_match(&mut self) {
+        self.recorded(Some(Intervention::ResetMatch), |gc| {
+            gc.delayed_commands.clear();
+            gc.simu.reset();
+            gc.referee.reset(gc.simu.t);
+        })
     }
     pub fn kick(&mut self, id: Robot, f: f64) {
-        self.simu.kick(id, f)
+        self.recorded(Some(Intervention::Kick(id, f)), |gc| gc.simu.kick(id, f))
     }
     pub fn get_kicker_pose(&self, id: Robot) -> Pose {
         let pos = self.simu.bodies[self.simu.kickers[id as usize]].position();
@@ -1,12 +1,25 @@
 //! A GUI built with Bevy that can run nativelly or in wasm
 
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::f64::consts::PI;
 use std::rc::Rc;
 
 use crate::constants::real::*;
 use crate::GC;
-use crate::game_state::Robot;
+use crate::debug::{DebugDrawings, Shape};
+use crate::render;
+use crate::game_state::{GameState, Pose, Robot, Team};
+#[cfg(not(feature = "alternative_http_client"))]
+use crate::native::gc::Intervention;
+#[cfg(not(feature = "alternative_http_client"))]
+use crate::referee::PENALTY_REASONS;
+#[cfg(feature = "record")]
+use crate::native::replay::Replay;
+#[cfg(feature = "record")]
+use crate::referee::RefereeEvent;
+use bevy::ecs::system::SystemParam;
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
 use bevy::log::LogPlugin;
 use bevy::window::PrimaryWindow;
 /// Bevy is only used to visualize the simulation
@@ -17,13 +30,113 @@ use bevy::{
     window::WindowResolution,
 };
 use rapier2d_f64::prelude::*;
+#[cfg(not(feature = "alternative_http_client"))]
+use tracing::warn;
 
 const WINDOW_SCALE: f32 = 400. as f32;
-const LINE_WIDTH: f32 = 0.02;
-const GREEN: Color = Color::rgb(68./255., 170./255., 1./255.);
+const LINE_WIDTH: f32 = render::LINE_WIDTH as f32;
+const GREEN: Color = color(render::GRASS_COLOR);
+/// Height of the timeline in replay mode, in pixels
+#[cfg(feature = "record")]
+const TIMELINE_HEIGHT: f32 = 16.;
+
+const BLUE_COLOR: Color = color(render::BLUE_COLOR);
+const GREEN_COLOR: Color = color(render::GREEN_COLOR);
+const BALL_COLOR: Color = color(render::BALL_COLOR);
+
+const fn color(c: [f32; 3]) -> Color {
+    Color::srgb(c[0], c[1], c[2])
+}
+
+/// Rotation of a dragged robot per step of the mouse wheel
+const ROTATION_STEP: f64 = PI / 12.;
+/// Duration of the mouse movement used for the velocity of a flicked ball, in seconds
+#[cfg(not(feature = "alternative_http_client"))]
+const FLICK_WINDOW: f32 = 0.1;
+/// In m/s
+#[cfg(not(feature = "alternative_http_client"))]
+const FLICK_MAX_SPEED: f32 = 5.;
+/// Zoom per step of the mouse wheel
+const ZOOM_FACTOR: f32 = 0.9;
+/// Smallest scale of the camera projection (1. shows the whole carpet)
+const MIN_ZOOM: f32 = 0.1;
+
+/// Duration of the trails, in seconds
+const TRAIL_DURATION: f32 = 3.;
+/// Movement between two frames above which a trail restarts (teleportation or jump in a replay), in meters
+const TRAIL_JUMP: f32 = 0.3;
+/// Duration of the predicted ball path, in seconds
+const BALL_PATH_DURATION: f64 = 3.;
 
 struct BevyGC(GC);
 
+/// Last game state shown, used by the HUD
+#[derive(Resource, Default)]
+struct DisplayedState(GameState);
+
+/// Materials of the robots [blue, green] and of the penalized robots
+#[derive(Resource)]
+struct RobotMaterials {
+    teams: [Handle<ColorMaterial>; 2],
+    penalized: Handle<ColorMaterial>,
+}
+
+/// Name and score of the team on the left or on the right of the field
+#[derive(Component)]
+struct HudTeam {
+    left: bool,
+}
+
+/// Match clock and state message
+#[derive(Component)]
+struct HudClock;
+
+/// Remaining time and reason of the penalty, above the robot
+#[derive(Component)]
+struct PenaltyLabel(Robot);
+
+/// Last positions of the robots and of the ball
+#[derive(Resource, Default)]
+struct Trails {
+    /// Toggled with T
+    enabled: bool,
+    /// Predicted path of the ball, toggled with B
+    ball_path: bool,
+    /// (time, position) of [blue1, blue2, green1, green2, ball]
+    positions: [VecDeque<(f32, Vec2)>; 5],
+}
+
+/// Linear damping of the ball used by its predicted path : the one of the simulation, or of the recording in replay mode
+#[derive(Resource)]
+struct BallDamping(f64);
+impl Default for BallDamping {
+    fn default() -> Self {
+        BallDamping(BALL_DAMPING)
+    }
+}
+
+/// Debug drawings shown over the field
+#[derive(Resource, Default)]
+struct DisplayedDebug(DebugDrawings);
+
+/// Debug layers (team, namespace) hidden with the debug panel
+#[derive(Resource, Default)]
+struct DebugVisibility {
+    hidden: Vec<(Team, String)>,
+}
+
+/// Column of the debug layer toggles
+#[derive(Component)]
+struct DebugPanel;
+
+/// Shows or hides a debug layer
+#[derive(Component)]
+struct DebugToggle(Team, String);
+
+/// Text primitive of a debug layer
+#[derive(Component)]
+struct DebugLabel;
+
 #[derive(Component)]
 struct Ball;
 
@@ -69,33 +182,12 @@ fn setup(
         ..default()
     });
 
-    for (i, (x, y, shape, color)) in [
-        // Defense areas
-        ((FIELD.0-DEFENSE_AREA.0)/2., DEFENSE_AREA.1/2., Rectangle::new(DEFENSE_AREA.0 as f32 + LINE_WIDTH, LINE_WIDTH), Color::BLACK),
-        ((FIELD.0-DEFENSE_AREA.0)/2., -DEFENSE_AREA.1/2., Rectangle::new(DEFENSE_AREA.0 as f32 + LINE_WIDTH, LINE_WIDTH), Color::BLACK),
-        ((FIELD.0/2.)-DEFENSE_AREA.0, 0., Rectangle::new(LINE_WIDTH, DEFENSE_AREA.1 as f32 + LINE_WIDTH), Color::BLACK),
-        (-(FIELD.0-DEFENSE_AREA.0)/2., DEFENSE_AREA.1/2., Rectangle::new(DEFENSE_AREA.0 as f32 + LINE_WIDTH, LINE_WIDTH), Color::BLACK),
-        (-(FIELD.0-DEFENSE_AREA.0)/2., -DEFENSE_AREA.1/2., Rectangle::new(DEFENSE_AREA.0 as f32 + LINE_WIDTH, LINE_WIDTH), Color::BLACK),
-        (DEFENSE_AREA.0-(FIELD.0/2.), 0., Rectangle::new(LINE_WIDTH, DEFENSE_AREA.1 as f32 + LINE_WIDTH), Color::BLACK),
-
-        // Borders of the field
-        (0., FIELD.1/2., Rectangle::new(FIELD.0 as f32 + LINE_WIDTH, LINE_WIDTH), Color::WHITE),
-        (0., -FIELD.1/2., Rectangle::new(FIELD.0 as f32 + LINE_WIDTH, LINE_WIDTH), Color::WHITE),
-        (FIELD.0/2., 0., Rectangle::new(LINE_WIDTH, FIELD.1 as f32 + LINE_WIDTH), Color::WHITE),
-        (-FIELD.0/2., 0., Rectangle::new(LINE_WIDTH, FIELD.1 as f32 + LINE_WIDTH), Color::WHITE),
-
-        // Goals
-        (GREEN_GOAL.0.x, GREEN_GOAL.0.y, Rectangle::new(LINE_WIDTH, 0.01), Color::BLACK),
-        (GREEN_GOAL.1.x, GREEN_GOAL.1.y, Rectangle::new(LINE_WIDTH, 0.01), Color::BLACK),
-        (BLUE_GOAL.0.x, BLUE_GOAL.0.y, Rectangle::new(LINE_WIDTH, 0.01), Color::BLACK),
-        (BLUE_GOAL.1.x, BLUE_GOAL.1.y, Rectangle::new(LINE_WIDTH, 0.01), Color::BLACK),
-
-        
-    ].into_iter().enumerate() {
+    // Same geometry as the software renderer
+    for (i, r) in render::field_rectangles().into_iter().enumerate() {
         cmds.spawn(MaterialMesh2dBundle {
-            mesh: Mesh2dHandle(meshes.add(shape)),
-            material: color_materials.add(color),
-            transform: Transform::from_xyz(x as f32, y as f32, -1. + (i as f32 * 0.000001)),
+            mesh: Mesh2dHandle(meshes.add(Rectangle::new(r.width as f32, r.height as f32))),
+            material: color_materials.add(color(r.color)),
+            transform: Transform::from_xyz(r.x as f32, r.y as f32, -1. + (i as f32 * 0.000001)),
             ..default()
         });
     }
@@ -106,7 +198,7 @@ fn setup(
             mesh: Mesh2dHandle(meshes.add(Circle {
                 radius: BALL_RADIUS as f32
             })),
-            material: color_materials.add(Color::srgb_u8(247, 107, 49)),
+            material: color_materials.add(BALL_COLOR),
             transform: Transform::from_xyz(DEFAULT_BALL_POS.x  as f32, DEFAULT_BALL_POS.y  as f32, 1.),
             ..default()
         },
@@ -117,9 +209,13 @@ fn setup(
     let hexagon = Mesh2dHandle(meshes.add(RegularPolygon::new(ROBOT_RADIUS  as f32, 6)));
     let rect = Mesh2dHandle(meshes.add(Rectangle::new(0.01, ROBOT_RADIUS as f32))); //ROBOT_RADIUS as f32 * 0.866, ROBOT_RADIUS as f32 * 0.5, (ROBOT_RADIUS as f32 * 0.866)+(KICKER_THICKNESS as f32), ROBOT_RADIUS as f32 * 0.5)));
 
-    let blue = color_materials.add(Color::srgb_u8(0, 0, 255));
-    let green = color_materials.add(Color::srgb_u8(0, 255, 0));
-    let grey = color_materials.add(Color::srgb(0.5, 0.5, 0.5));
+    let blue = color_materials.add(BLUE_COLOR);
+    let green = color_materials.add(GREEN_COLOR);
+    let grey = color_materials.add(color(render::KICKER_COLOR));
+    cmds.insert_resource(RobotMaterials {
+        teams: [blue.clone(), green.clone()],
+        penalized: color_materials.add(color(render::PENALIZED_COLOR))
+    });
     for r in Robot::all() {
         let pos = DEFAULT_ROBOTS_POS[r as usize];
         let material = match r {
@@ -150,31 +246,372 @@ fn move_objects(
     mut robots: Query<(&Robot, &mut Transform, &Children), Without<Ball>>,
     mut kickers: Query<&mut Transform, (With<Kicker>, Without<Ball>, Without<Robot>)>,
     gc: NonSendMut<BevyGC>,
+    mut displayed: ResMut<DisplayedState>,
+    #[cfg(not(feature = "alternative_http_client"))]
+    mut damping: ResMut<BallDamping>,
 ) {
+    // For the spectators, the game state comes from the client that runs the session
+    #[cfg(not(feature = "alternative_http_client"))]
+    let spectated = gc.0.spectated_state();
+    #[cfg(not(feature = "alternative_http_client"))]
+    let gs = spectated.clone().unwrap_or_else(|| gc.0.get_game_state());
+    #[cfg(feature = "alternative_http_client")]
     let gs = gc.0.get_game_state();
+    #[cfg(not(feature = "alternative_http_client"))]
+    if damping.0 != gc.0.simu.params.ball_damping {
+        damping.0 = gc.0.simu.params.ball_damping;
+    }
 
     if let Some(ball_pos) = gs.ball {
         *ball.single_mut() = Transform::from_xyz(ball_pos.x as f32, ball_pos.y as f32, 1.);
     }
 
     for (r, mut pos, childs) in robots.iter_mut() {
-        let new_pos = match r {
-            Robot::Blue1 => &gs.markers.blue1,
-            Robot::Blue2 => &gs.markers.blue2,
-            Robot::Green1 => &gs.markers.green1,
-            Robot::Green2 => &gs.markers.green2,
-        };
-        *pos = Transform::from_xyz(new_pos.position.x as f32, new_pos.position.y as f32, 1.).looking_to(Vec3::ZERO, Vec3::new((new_pos.orientation + (PI/2.)).cos() as f32, (new_pos.orientation + (PI/2.)).sin() as f32, 0.));    
+        let new_pos = gs.markers.get(*r);
+        *pos = robot_transform(new_pos);
 
-        // In alternative_http mode, the kicker movement is not shown
+        // In alternative_http mode and for the spectators, the kicker movement is not shown
         #[cfg(not(feature = "alternative_http_client"))]
-        {
+        if spectated.is_none() {
             let mut kicker_pos = kickers.get_mut(childs[0]).unwrap();
             let pose = gc.0.get_kicker_pose(*r);
             let d = ((pose.position.x - new_pos.position.x).powi(2) + (pose.position.y - new_pos.position.y).powi(2)).sqrt();
             *kicker_pos = Transform::from_xyz(d as f32, 0., 1.);
         }
     }
+    displayed.0 = gs;
+}
+
+fn setup_hud(mut cmds: Commands) {
+    let style = TextStyle {
+        font_size: 24.,
+        color: Color::WHITE,
+        ..default()
+    };
+    cmds.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(0.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::SpaceBetween,
+            padding: UiRect::axes(Val::Px(10.), Val::Px(5.)),
+            ..default()
+        },
+        background_color: Color::srgba(0., 0., 0., 0.4).into(),
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn((TextBundle::from_section("", style.clone()), HudTeam { left: true }));
+        parent.spawn((TextBundle::from_section("", style.clone()).with_text_justify(JustifyText::Center), HudClock));
+        parent.spawn((TextBundle::from_section("", style.clone()), HudTeam { left: false }));
+    });
+    for r in Robot::all() {
+        cmds.spawn((Text2dBundle {
+            text: Text::from_section("", TextStyle {
+                font_size: 14.,
+                color: Color::WHITE,
+                ..default()
+            }),
+            ..default()
+        }, PenaltyLabel(r)));
+    }
+}
+
+fn update_hud(
+    displayed: Res<DisplayedState>,
+    materials: Res<RobotMaterials>,
+    mut teams: Query<(&HudTeam, &mut Text), (Without<HudClock>, Without<PenaltyLabel>)>,
+    mut clock: Query<&mut Text, (With<HudClock>, Without<HudTeam>, Without<PenaltyLabel>)>,
+    mut labels: Query<(&PenaltyLabel, &mut Text, &mut Transform), (Without<HudTeam>, Without<HudClock>)>,
+    mut robots: Query<(&Robot, &mut Handle<ColorMaterial>)>,
+) {
+    let gs = &displayed.0;
+    let referee = &gs.referee;
+    for (side, mut text) in teams.iter_mut() {
+        // The team on the left defends the negative x
+        let team = if referee.teams.blue.x_positive != side.left {Team::Blue} else {Team::Green};
+        let referee_team = referee.teams.get(team);
+        let name = match (referee_team.name.as_str(), team) {
+            ("", Team::Blue) => "Blue",
+            ("", Team::Green) => "Green",
+            (name, _) => name
+        };
+        let section = &mut text.sections[0];
+        section.value = if side.left {
+            format!("{}  {}", name, referee_team.score)
+        } else {
+            format!("{}  {}", referee_team.score, name)
+        };
+        section.style.color = match team {
+            Team::Blue => BLUE_COLOR,
+            Team::Green => GREEN_COLOR
+        };
+    }
+    let state = if !referee.game_state_msg.is_empty() {
+        referee.game_state_msg.as_str()
+    } else if referee.game_is_running {
+        "Game running"
+    } else if referee.game_paused {
+        "Game paused"
+    } else if referee.halftime_is_running {
+        "Halftime"
+    } else {
+        ""
+    };
+    clock.single_mut().sections[0].value = format!("{:02}:{:02}\n{}", referee.timer / 60, referee.timer % 60, state);
+
+    for (label, mut text, mut transform) in labels.iter_mut() {
+        let robot = referee.teams.robot(label.0);
+        let pose = gs.markers.get(label.0);
+        text.sections[0].value = if robot.penalized {
+            format!("{} s\n{}", robot.penalized_remaining.unwrap_or(0), robot.penalized_reason.as_deref().unwrap_or(""))
+        } else {
+            String::new()
+        };
+        // Text2d sizes are in pixels, the world is in meters
+        *transform = Transform::from_xyz(pose.position.x as f32, (pose.position.y + ROBOT_RADIUS*2.) as f32, 3.)
+            .with_scale(Vec3::splat(1. / WINDOW_SCALE));
+    }
+    for (r, mut material) in robots.iter_mut() {
+        *material = if referee.teams.robot(*r).penalized {
+            materials.penalized.clone()
+        } else {
+            materials.teams[r.team() as usize].clone()
+        };
+    }
+}
+
+#[cfg(not(feature = "alternative_http_client"))]
+fn update_live_debug(
+    gc: NonSend<BevyGC>,
+    mut displayed: ResMut<DisplayedDebug>,
+) {
+    // Compared first so that the labels and the panel are only rebuilt on changes
+    if displayed.0 != gc.0.debug {
+        displayed.0 = gc.0.debug.clone();
+    }
+}
+
+fn draw_debug(
+    mut cmds: Commands,
+    mut gizmos: Gizmos,
+    displayed: Res<DisplayedDebug>,
+    visibility: Res<DebugVisibility>,
+    labels: Query<Entity, With<DebugLabel>>,
+) {
+    let rebuild_labels = displayed.is_changed() || visibility.is_changed();
+    if rebuild_labels {
+        for entity in labels.iter() {
+            cmds.entity(entity).despawn();
+        }
+    }
+    let point = |p: nalgebra::Point2<f64>| Vec2::new(p.x as f32, p.y as f32);
+    for layer in displayed.0.layers.iter() {
+        if visibility.hidden.iter().any(|(team, namespace)| *team == layer.team && *namespace == layer.namespace) {
+            continue
+        }
+        for primitive in layer.primitives.iter() {
+            let color = match (primitive.color, layer.team) {
+                (Some(c), _) => color(c),
+                (None, Team::Blue) => BLUE_COLOR,
+                (None, Team::Green) => GREEN_COLOR
+            };
+            match &primitive.shape {
+                Shape::Point { position } => {
+                    gizmos.circle_2d(point(*position), 0.01, color);
+                },
+                Shape::Line { from, to } => gizmos.line_2d(point(*from), point(*to), color),
+                Shape::Circle { center, radius } => {
+                    gizmos.circle_2d(point(*center), *radius as f32, color);
+                },
+                Shape::Arrow { from, to } => {
+                    gizmos.arrow_2d(point(*from), point(*to), color);
+                },
+                Shape::Text { position, text } => if rebuild_labels {
+                    cmds.spawn((Text2dBundle {
+                        text: Text::from_section(text.clone(), TextStyle {
+                            font_size: 14.,
+                            color,
+                            ..default()
+                        }),
+                        // Text2d sizes are in pixels, the world is in meters
+                        transform: Transform::from_xyz(position.x as f32, position.y as f32, 4.)
+                            .with_scale(Vec3::splat(1. / WINDOW_SCALE)),
+                        ..default()
+                    }, DebugLabel));
+                }
+            }
+        }
+    }
+}
+
+fn setup_debug_panel(mut cmds: Commands) {
+    cmds.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(45.),
+            left: Val::Px(5.),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(3.),
+            ..default()
+        },
+        ..default()
+    }, DebugPanel));
+}
+
+/// One toggle per debug layer, rebuilt when the list of layers changes
+fn update_debug_panel(
+    mut cmds: Commands,
+    displayed: Res<DisplayedDebug>,
+    panel: Query<Entity, With<DebugPanel>>,
+    mut shown: Local<Vec<(Team, String)>>,
+) {
+    if !displayed.is_changed() {
+        return
+    }
+    let layers: Vec<(Team, String)> = displayed.0.layers.iter().map(|l| (l.team, l.namespace.clone())).collect();
+    if layers == *shown {
+        return
+    }
+    let panel = panel.single();
+    cmds.entity(panel).despawn_descendants();
+    cmds.entity(panel).with_children(|parent| {
+        for (team, namespace) in layers.iter() {
+            let color = match team {
+                Team::Blue => BLUE_COLOR,
+                Team::Green => GREEN_COLOR
+            };
+            parent.spawn((ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(8.), Val::Px(3.)),
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            }, DebugToggle(*team, namespace.clone()))).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(namespace.clone(), TextStyle {
+                    font_size: 16.,
+                    color,
+                    ..default()
+                }));
+            });
+        }
+    });
+    *shown = layers;
+}
+
+fn debug_toggles(
+    mut visibility: ResMut<DebugVisibility>,
+    mut interactions: Query<(&Interaction, &DebugToggle, &mut BackgroundColor), Changed<Interaction>>,
+) {
+    for (interaction, toggle, mut color) in interactions.iter_mut() {
+        if *interaction == Interaction::Pressed {
+            let layer = (toggle.0, toggle.1.clone());
+            match visibility.hidden.iter().position(|l| *l == layer) {
+                Some(i) => {
+                    visibility.hidden.remove(i);
+                },
+                None => visibility.hidden.push(layer)
+            }
+        }
+        let hidden = visibility.hidden.iter().any(|(team, namespace)| *team == toggle.0 && *namespace == toggle.1);
+        *color = match (interaction, hidden) {
+            (Interaction::None, false) => BUTTON_COLOR,
+            (Interaction::None, true) => Color::srgba(0.1, 0.1, 0.1, 0.3),
+            _ => BUTTON_HOVERED_COLOR
+        }.into();
+    }
+}
+
+fn toggle_trails(
+    mut trails: ResMut<Trails>,
+    keys: Res<ButtonInput<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::KeyT) {
+        trails.enabled = !trails.enabled;
+    }
+    if keys.just_pressed(KeyCode::KeyB) {
+        trails.ball_path = !trails.ball_path;
+    }
+}
+
+/// Also recorded while hidden, for the velocity of the ball and to show the trails as soon as they are enabled
+fn update_trails(
+    time: Res<Time>,
+    displayed: Res<DisplayedState>,
+    mut trails: ResMut<Trails>,
+) {
+    let now = time.elapsed_seconds();
+    let gs = &displayed.0;
+    let mut positions = Robot::all().map(|r| {
+        let pose = gs.markers.get(r);
+        Some(Vec2::new(pose.position.x as f32, pose.position.y as f32))
+    }).to_vec();
+    positions.push(gs.ball.map(|b| Vec2::new(b.x as f32, b.y as f32)));
+    for (trail, position) in trails.positions.iter_mut().zip(positions) {
+        if let Some(position) = position {
+            if trail.back().is_some_and(|(_, last)| last.distance(position) > TRAIL_JUMP) {
+                trail.clear();
+            }
+            trail.push_back((now, position));
+        }
+        while trail.front().is_some_and(|(t, _)| now - t > TRAIL_DURATION) {
+            trail.pop_front();
+        }
+    }
+}
+
+fn draw_trails(
+    time: Res<Time>,
+    trails: Res<Trails>,
+    displayed: Res<DisplayedState>,
+    damping: Res<BallDamping>,
+    mut gizmos: Gizmos,
+) {
+    let now = time.elapsed_seconds();
+    if trails.enabled {
+        for (i, trail) in trails.positions.iter().enumerate() {
+            let color = match i {
+                0 | 1 => BLUE_COLOR,
+                2 | 3 => GREEN_COLOR,
+                _ => BALL_COLOR
+            };
+            // Fades out with the age of the positions
+            gizmos.linestrip_gradient_2d(trail.iter().map(|(t, p)| (*p, color.with_alpha(1. - (now - t) / TRAIL_DURATION))));
+        }
+    }
+    if trails.ball_path {
+        let Some(ball) = displayed.0.ball else {
+            return
+        };
+        // Velocity of the extended game state, or from the last two positions
+        let velocity = match &displayed.0.extended {
+            Some(extended) => extended.ball_velocity,
+            None => {
+                let trail = &trails.positions[4];
+                match (trail.len().checked_sub(2).and_then(|i| trail.get(i)), trail.back()) {
+                    (Some((t0, p0)), Some((t1, p1))) if t1 > t0 => {
+                        let v = (*p1 - *p0) / (t1 - t0);
+                        nalgebra::Vector2::new(v.x as f64, v.y as f64)
+                    },
+                    _ => return
+                }
+            }
+        };
+        if velocity.norm() < 0.01 {
+            return
+        }
+        // With a linear damping, v(t) = v0 * exp(-damping * t). The rebounds are ignored
+        let damping = damping.0;
+        let points = (0..=60).map(|i| i as f64 * BALL_PATH_DURATION / 60.)
+            .map(|t| ball + velocity * (1. - (-damping * t).exp()) / damping)
+            .take_while(|p| p.x.abs() < FIELD.0 / 2. && p.y.abs() < FIELD.1 / 2.)
+            .map(|p| Vec2::new(p.x as f32, p.y as f32));
+        gizmos.linestrip_2d(points, BALL_COLOR.with_alpha(0.7));
+    }
+}
+
+fn robot_transform(pose: &Pose) -> Transform {
+    Transform::from_xyz(pose.position.x as f32, pose.position.y as f32, 1.).looking_to(Vec3::ZERO, Vec3::new((pose.orientation + (PI/2.)).cos() as f32, (pose.orientation + (PI/2.)).sin() as f32, 0.))
 }
 
 #[cfg(not(feature = "alternative_http_client"))]
@@ -185,22 +622,53 @@ struct Dragging(Option<RigidBodyHandle>);
 #[derive(Default)]
 struct Dragging(Rc<RefCell<Option<RigidBodyHandle>>>);
 
+impl Dragging {
+    #[cfg(not(feature = "alternative_http_client"))]
+    fn entity(&self) -> Option<RigidBodyHandle> {
+        self.0
+    }
+    #[cfg(feature = "alternative_http_client")]
+    fn entity(&self) -> Option<RigidBodyHandle> {
+        *self.0.borrow()
+    }
+}
+
+/// Orientation given with the mouse wheel to the dragged robot, None to keep its orientation
+#[derive(Resource, Default)]
+struct DragOrientation(Option<f64>);
+
+/// Flick mode (toggled with F) : the ball keeps the velocity of the mouse when it is released
+#[cfg(not(feature = "alternative_http_client"))]
+#[derive(Resource, Default)]
+struct Flick {
+    enabled: bool,
+    /// (time, position) of the dragged ball
+    positions: VecDeque<(f32, Vec2)>,
+}
+
+/// Robot turned towards the mouse with a right drag
+#[cfg(not(feature = "alternative_http_client"))]
+#[derive(Resource, Default)]
+struct Aiming(Option<Robot>);
+
 fn select_dragging(
     mut gc: NonSendMut<BevyGC>,
     mut dragging: NonSendMut<Dragging>,
-    q_windows: Query<&Window, With<PrimaryWindow>>,
+    cursor: FieldCursor,
     buttons: Res<ButtonInput<MouseButton>>,
+    ui: Query<&Interaction>,
 ) {
-    if buttons.just_pressed(MouseButton::Left) {
-        if let Some(position) = q_windows.single().cursor_position() {
+    // Clicks on the buttons of the GUI don't move anything
+    if buttons.just_pressed(MouseButton::Left) && ui.iter().all(|i| *i == Interaction::None) {
+        if let Some(position) = cursor.position() {
             #[cfg(not(feature = "alternative_http_client"))]
             {
-                let entity = gc.0.find_entity_at(bevy_to_simu(position));
+                let entity = gc.0.find_entity_at(position);
                 *dragging = Dragging(Some(entity.unwrap_or(gc.0.get_ball_handle())))
             }
             #[cfg(feature = "alternative_http_client")]
             {
-                gc.0.find_entity_at_rc(bevy_to_simu(position), dragging.0.clone(), Some(gc.0.get_ball_handle()));
+                gc.0.find_entity_at_rc(position, dragging.0.clone(), Some(gc.0.get_ball_handle()));
             }
         }
     } else if !buttons.pressed(MouseButton::Left) { // better in async because find_entity_at_rc can update dragging after mouse release
@@ -215,22 +683,70 @@ fn select_dragging(
     }
 }
 
+/// The mouse wheel turns the dragged robot
 fn update_dragging(
     mut gc: NonSendMut<BevyGC>,
-    q_windows: Query<&Window, With<PrimaryWindow>>,
+    cursor: FieldCursor,
     dragging: NonSend<Dragging>,
+    displayed: Res<DisplayedState>,
+    mut orientation: ResMut<DragOrientation>,
+    mut wheel: EventReader<MouseWheel>,
 ) {
-    #[cfg(not(feature = "alternative_http_client"))]
-    let entity = dragging.0;
-    #[cfg(feature = "alternative_http_client")]
-    let entity = *dragging.0.borrow();
-    match entity {
-        None => (),
+    let scroll: f32 = wheel.read().map(|e| match e.unit {
+        MouseScrollUnit::Line => e.y,
+        MouseScrollUnit::Pixel => e.y / 100.
+    }).sum();
+    match dragging.entity() {
+        None => orientation.0 = None,
         Some(d) => {
-            if let Some(position) = q_windows.single().cursor_position() {
-                gc.0.teleport_entity(d, bevy_to_simu(position), None);
+            if scroll != 0. {
+                if let Some(r) = Robot::all().into_iter().find(|r| gc.0.get_robot_handle(*r) == d) {
+                    let current = orientation.0.unwrap_or(displayed.0.markers.get(r).orientation);
+                    orientation.0 = Some(current + scroll as f64 * ROTATION_STEP);
+                }
+            }
+            if let Some(position) = cursor.position() {
+                gc.0.teleport_entity(d, position, orientation.0);
+            }
+        }
+    }
+}
+
+/// Runs after `update_dragging` and before `select_dragging` : on release, the ball is still the dragged entity
+#[cfg(not(feature = "alternative_http_client"))]
+fn flick_ball(
+    mut gc: NonSendMut<BevyGC>,
+    mut flick: ResMut<Flick>,
+    dragging: NonSend<Dragging>,
+    cursor: FieldCursor,
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+) {
+    if keys.just_pressed(KeyCode::KeyF) {
+        flick.enabled = !flick.enabled;
+        info!("Flick mode : {}", flick.enabled);
+    }
+    let ball = gc.0.get_ball_handle();
+    if !flick.enabled || dragging.entity() != Some(ball) {
+        flick.positions.clear();
+        return
+    }
+    let now = time.elapsed_seconds();
+    if let Some(position) = cursor.position() {
+        flick.positions.push_back((now, Vec2::new(position.x as f32, position.y as f32)));
+    }
+    while flick.positions.front().is_some_and(|(t, _)| now - t > FLICK_WINDOW) {
+        flick.positions.pop_front();
+    }
+    if buttons.just_released(MouseButton::Left) {
+        if let (Some((t0, p0)), Some((t1, p1))) = (flick.positions.front(), flick.positions.back()) {
+            if t1 > t0 {
+                let velocity = ((*p1 - *p0) / (t1 - t0)).clamp_length_max(FLICK_MAX_SPEED);
+                gc.0.set_velocity(ball, vector![velocity.x as f64, velocity.y as f64]);
             }
         }
+        flick.positions.clear();
     }
 }
 
@@ -255,12 +771,302 @@ fn kick(
         gc.0.all_kick();
     }
 }
-fn penalize(
+
+#[cfg(not(feature = "alternative_http_client"))]
+#[derive(Component, Clone, Copy)]
+enum RefereeButton {
+    Start,
+    Pause,
+    Resume,
+    Halftime,
+    Shootout,
+    CancelGoal,
+    Reset,
+    CenterBall,
+    Penalize,
+    NextReason,
+    Unpenalize,
+}
+#[cfg(not(feature = "alternative_http_client"))]
+impl RefereeButton {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Start => "Start",
+            Self::Pause => "Pause",
+            Self::Resume => "Resume",
+            Self::Halftime => "Halftime",
+            Self::Shootout => "Penalty shootout",
+            Self::CancelGoal => "Cancel goal",
+            Self::Reset => "Reset positions",
+            Self::CenterBall => "Ball to center",
+            Self::Penalize => "Penalize",
+            Self::NextReason => "",
+            Self::Unpenalize => "Unpenalize",
+        }
+    }
+}
+
+/// Robot and penalty reason chosen in the referee panel
+#[cfg(not(feature = "alternative_http_client"))]
+#[derive(Resource)]
+struct RefereePanel {
+    selected: Robot,
+    /// Index in PENALTY_REASONS
+    reason: usize,
+}
+
+#[cfg(not(feature = "alternative_http_client"))]
+#[derive(Component)]
+struct PanelRobotText;
+
+#[cfg(not(feature = "alternative_http_client"))]
+#[derive(Component)]
+struct PanelReasonText;
+
+const BUTTON_COLOR: Color = Color::srgba(0.1, 0.1, 0.1, 0.8);
+const BUTTON_HOVERED_COLOR: Color = Color::srgba(0.3, 0.3, 0.3, 0.9);
+
+#[cfg(not(feature = "alternative_http_client"))]
+fn setup_referee_panel(mut cmds: Commands) {
+    let style = TextStyle {
+        font_size: 16.,
+        color: Color::WHITE,
+        ..default()
+    };
+    cmds.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(45.),
+            right: Val::Px(5.),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(3.),
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        for button in [
+            RefereeButton::Start, RefereeButton::Pause, RefereeButton::Resume, RefereeButton::Halftime, RefereeButton::Shootout,
+            RefereeButton::CancelGoal, RefereeButton::Reset, RefereeButton::CenterBall
+        ] {
+            spawn_button(parent, button, &style);
+        }
+        parent.spawn((TextBundle::from_section("", style.clone()), PanelRobotText));
+        spawn_button(parent, RefereeButton::NextReason, &style);
+        spawn_button(parent, RefereeButton::Penalize, &style);
+        spawn_button(parent, RefereeButton::Unpenalize, &style);
+    });
+}
+
+#[cfg(not(feature = "alternative_http_client"))]
+fn spawn_button(parent: &mut ChildBuilder, button: RefereeButton, style: &TextStyle) {
+    parent.spawn((ButtonBundle {
+        style: Style {
+            padding: UiRect::axes(Val::Px(8.), Val::Px(3.)),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        background_color: BUTTON_COLOR.into(),
+        ..default()
+    }, button)).with_children(|parent| {
+        let mut text = parent.spawn(TextBundle::from_section(button.label(), style.clone()));
+        if let RefereeButton::NextReason = button {
+            text.insert(PanelReasonText);
+        }
+    });
+}
+
+#[cfg(not(feature = "alternative_http_client"))]
+fn referee_panel(
     mut gc: NonSendMut<BevyGC>,
-    keys: Res<ButtonInput<KeyCode>>
+    mut panel: ResMut<RefereePanel>,
+    mut interactions: Query<(&Interaction, &RefereeButton, &mut BackgroundColor), Changed<Interaction>>,
+) {
+    for (interaction, button, mut color) in interactions.iter_mut() {
+        *color = match interaction {
+            Interaction::None => BUTTON_COLOR,
+            _ => BUTTON_HOVERED_COLOR
+        }.into();
+        if *interaction != Interaction::Pressed {
+            continue
+        }
+        let gc = &mut gc.0;
+        match button {
+            RefereeButton::Start => {
+                gc.intervene(Intervention::Start);
+            },
+            RefereeButton::Pause => {
+                gc.intervene(Intervention::Pause);
+            },
+            RefereeButton::Resume => {
+                gc.intervene(Intervention::Resume);
+            },
+            RefereeButton::Halftime => {
+                gc.intervene(Intervention::Halftime);
+            },
+            // The team of the selected robot kicks first
+            RefereeButton::Shootout => gc.start_shootout(panel.selected.team()),
+            RefereeButton::CancelGoal => if !gc.intervene(Intervention::CancelGoal) {
+                info!("No goal to cancel");
+            },
+            RefereeButton::Reset => gc.reset(),
+            RefereeButton::CenterBall => gc.teleport_entity(gc.get_ball_handle(), DEFAULT_BALL_POS, None),
+            RefereeButton::Penalize => gc.penalize(panel.selected, PENALTY_REASONS[panel.reason]),
+            RefereeButton::NextReason => panel.reason = (panel.reason + 1) % PENALTY_REASONS.len(),
+            RefereeButton::Unpenalize => {
+                gc.intervene(Intervention::Unpenalize(panel.selected));
+            },
+        }
+    }
+}
+
+/// Right click on a robot selects it in the referee panel. Dragging with the right button turns it towards the mouse
+#[cfg(not(feature = "alternative_http_client"))]
+fn select_panel_robot(
+    mut gc: NonSendMut<BevyGC>,
+    mut panel: ResMut<RefereePanel>,
+    mut aiming: ResMut<Aiming>,
+    cursor: FieldCursor,
+    buttons: Res<ButtonInput<MouseButton>>,
+) {
+    if buttons.just_pressed(MouseButton::Right) {
+        if let Some(position) = cursor.position() {
+            if let Some(handle) = gc.0.find_entity_at(position) {
+                if let Some(r) = Robot::all().into_iter().find(|r| gc.0.get_robot_handle(*r) == handle) {
+                    panel.selected = r;
+                    aiming.0 = Some(r);
+                }
+            }
+        }
+    } else if !buttons.pressed(MouseButton::Right) {
+        aiming.0 = None;
+    }
+    if let (Some(r), Some(position)) = (aiming.0, cursor.position()) {
+        let robot = gc.0.get_game_state().markers.get(r).position;
+        let d = position - robot;
+        // Not while the mouse is still on the robot
+        if d.norm() > ROBOT_RADIUS {
+            let handle = gc.0.get_robot_handle(r);
+            gc.0.teleport_entity(handle, robot, Some(d.y.atan2(d.x)));
+        }
+    }
+}
+
+#[cfg(not(feature = "alternative_http_client"))]
+fn update_referee_panel(
+    panel: Res<RefereePanel>,
+    manual: Res<ManualControl>,
+    mut robot_text: Query<&mut Text, (With<PanelRobotText>, Without<PanelReasonText>)>,
+    mut reason_text: Query<&mut Text, (With<PanelReasonText>, Without<PanelRobotText>)>,
+) {
+    if !panel.is_changed() && !manual.is_changed() {
+        return
+    }
+    robot_text.single_mut().sections[0].value = match manual.robot {
+        Some(r) => format!("Robot : {:?} (right click)\nManual control : {:?} (M)", panel.selected, r),
+        None => format!("Robot : {:?} (right click)", panel.selected)
+    };
+    reason_text.single_mut().sections[0].value = format!("Reason : {}", PENALTY_REASONS[panel.reason]);
+}
+
+/// Preemption reason of the robot driven by hand
+#[cfg(not(feature = "alternative_http_client"))]
+const MANUAL_CONTROL: &str = "Manual control";
+
+/// Robot driven by hand, see `drive_manual_robot`
+#[cfg(not(feature = "alternative_http_client"))]
+#[derive(Resource, Default)]
+struct ManualControl {
+    robot: Option<Robot>,
+}
+
+/// True if the robot is driven for another reason than the manual control (penalty shootout, scenario)
+#[cfg(not(feature = "alternative_http_client"))]
+fn driven_by_other(driven: Option<&'static str>) -> bool {
+    driven.is_some_and(|reason| reason != MANUAL_CONTROL)
+}
+
+/// M toggles the manual control of the robot selected in the referee panel
+#[cfg(not(feature = "alternative_http_client"))]
+fn toggle_manual_control(
+    gc: NonSend<BevyGC>,
+    panel: Res<RefereePanel>,
+    mut manual: ResMut<ManualControl>,
+    keys: Res<ButtonInput<KeyCode>>,
 ) {
-    if keys.just_pressed(KeyCode::KeyP) {
-        gc.0.penalize(Robot::Blue1, "test");
+    if !keys.just_pressed(KeyCode::KeyM) {
+        return
+    }
+    let mut tasks = gc.0.referee.lock_tasks_mut();
+    if let Some(r) = manual.robot.take() {
+        // Meanwhile, the robot may have been taken by the referee
+        if !driven_by_other(tasks[r as usize].driven) {
+            tasks[r as usize].driven = None;
+            tasks[r as usize].control = (0., 0., 0.);
+        }
+        if r == panel.selected {
+            return
+        }
+    }
+    let task = &mut tasks[panel.selected as usize];
+    if driven_by_other(task.driven) {
+        warn!("{:?} can't be driven by hand : {}", panel.selected, task.driven.unwrap());
+        return
+    }
+    task.driven = Some(MANUAL_CONTROL);
+    manual.robot = Some(panel.selected);
+}
+
+/// Keyboard : WASD or arrows to move in the field frame, Q/E to rotate and space to kick.
+/// Gamepad : left stick to move, right stick to rotate and south button (A) to kick
+#[cfg(not(feature = "alternative_http_client"))]
+fn drive_manual_robot(
+    gc: NonSend<BevyGC>,
+    manual: Res<ManualControl>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+) {
+    let Some(r) = manual.robot else {
+        return
+    };
+    let pressed = |a: KeyCode, b: KeyCode| keys.pressed(a) || keys.pressed(b);
+    let mut direction = Vec2::ZERO;
+    if pressed(KeyCode::KeyW, KeyCode::ArrowUp) { direction.y += 1. }
+    if pressed(KeyCode::KeyS, KeyCode::ArrowDown) { direction.y -= 1. }
+    if pressed(KeyCode::KeyD, KeyCode::ArrowRight) { direction.x += 1. }
+    if pressed(KeyCode::KeyA, KeyCode::ArrowLeft) { direction.x -= 1. }
+    let mut rotation = 0.;
+    if keys.pressed(KeyCode::KeyQ) { rotation += 1. }
+    if keys.pressed(KeyCode::KeyE) { rotation -= 1. }
+    let mut kick = keys.just_pressed(KeyCode::Space);
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+        direction += Vec2::new(axis(GamepadAxisType::LeftStickX), axis(GamepadAxisType::LeftStickY));
+        rotation -= axis(GamepadAxisType::RightStickX);
+        kick |= gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South));
+    }
+    let direction = direction.clamp_length_max(1.);
+    let rotation = rotation.clamp(-1., 1.);
+
+    let gc = &gc.0;
+    // The commands are in the frame of the robot
+    let orientation = gc.simu.bodies[gc.get_robot_handle(r)].rotation().angle() as f32;
+    let direction = Vec2::from_angle(-orientation).rotate(direction) * gc.simu.params.robot_speed as f32;
+    let mut tasks = gc.referee.lock_tasks_mut();
+    let task = &mut tasks[r as usize];
+    // The penalty shootout and the scenarios keep the robots they drive
+    if driven_by_other(task.driven) {
+        return
+    }
+    // After a reset of the referee
+    task.driven = Some(MANUAL_CONTROL);
+    if task.penalty.is_some() {
+        return
+    }
+    task.control = (direction.x, direction.y, rotation * crate::constants::simu::ROBOT_ANGULAR_SPEED as f32);
+    if kick {
+        task.kick = Some(1.);
     }
 }
 
@@ -270,17 +1076,235 @@ fn step_simulation(
     gc.0.step();
 }
 
-fn bevy_to_simu(pos: Vec2) -> Point<f64> {
-    Point::new((pos.x / WINDOW_SCALE) as f64 - (CARPET.0/2.), -((pos.y / WINDOW_SCALE) as f64 - (CARPET.1/2.)))
+/// Run condition of the inputs that act on the simulation : a spectator only watches the session
+#[cfg(not(feature = "alternative_http_client"))]
+fn not_spectating(gc: NonSend<BevyGC>) -> bool {
+    gc.0.spectated_state().is_none()
+}
+#[cfg(feature = "alternative_http_client")]
+fn not_spectating() -> bool {
+    true
+}
+
+/// Position of the mouse on the field, through the camera (letterboxing, zoom and pan)
+#[derive(SystemParam)]
+struct FieldCursor<'w, 's> {
+    windows: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    cameras: Query<'w, 's, (&'static Camera, &'static GlobalTransform)>,
+}
+impl FieldCursor<'_, '_> {
+    fn position(&self) -> Option<Point<f64>> {
+        let position = self.windows.single().cursor_position()?;
+        let (camera, transform) = self.cameras.single();
+        let position = camera.viewport_to_world_2d(transform, position)?;
+        Some(Point::new(position.x as f64, position.y as f64))
+    }
+}
+
+/// Mouse wheel : zoom around the cursor, middle button : pan, V : reset the view
+fn camera_control(
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cursor: FieldCursor,
+    dragging: Option<NonSend<Dragging>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut motion: EventReader<MouseMotion>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+) {
+    let (mut transform, mut projection) = cameras.single_mut();
+    if keys.just_pressed(KeyCode::KeyV) {
+        projection.scale = 1.;
+        transform.translation.x = 0.;
+        transform.translation.y = 0.;
+    }
+
+    let scroll: f32 = wheel.read().map(|e| match e.unit {
+        MouseScrollUnit::Line => e.y,
+        MouseScrollUnit::Pixel => e.y / 100.
+    }).sum();
+    // While dragging, the mouse wheel turns the robot
+    let dragging = dragging.is_some_and(|d| d.entity().is_some());
+    if scroll != 0. && !dragging {
+        let scale = (projection.scale * ZOOM_FACTOR.powf(scroll)).clamp(MIN_ZOOM, 1.);
+        // The point under the cursor stays in place
+        if let Some(position) = cursor.position() {
+            let position = Vec2::new(position.x as f32, position.y as f32);
+            let translation = position + (transform.translation.truncate() - position) * scale / projection.scale;
+            transform.translation.x = translation.x;
+            transform.translation.y = translation.y;
+        }
+        projection.scale = scale;
+    }
+
+    let delta: Vec2 = motion.read().map(|e| e.delta).sum();
+    if buttons.pressed(MouseButton::Middle) && delta != Vec2::ZERO {
+        // Meters per pixel. The y axis of the window points down
+        let ratio = projection.area.width() / windows.single().width();
+        transform.translation.x -= delta.x * ratio;
+        transform.translation.y += delta.y * ratio;
+    }
 }
 
-fn simu_to_bevy(pos: Point<f64>) -> Vec2 {
-    Vec2::new((pos.x + (CARPET.0/2.)) as f32 * WINDOW_SCALE, (pos.y + (CARPET.1/2.)) as f32 * WINDOW_SCALE)
+#[cfg(feature = "record")]
+#[derive(Resource)]
+struct BevyReplay(Replay);
+
+#[cfg(feature = "record")]
+#[derive(Component)]
+struct ReplayText;
+
+#[cfg(feature = "record")]
+#[derive(Component)]
+struct TimelineCursor;
+
+#[cfg(feature = "record")]
+fn setup_replay(mut cmds: Commands) {
+    cmds.spawn((
+        TextBundle::from_section("", TextStyle {
+            font_size: 18.,
+            color: Color::WHITE,
+            ..default()
+        }).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(TIMELINE_HEIGHT + 5.),
+            left: Val::Px(5.),
+            ..default()
+        }),
+        ReplayText
+    ));
+    cmds.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(0.),
+            left: Val::Px(0.),
+            width: Val::Percent(100.),
+            height: Val::Px(TIMELINE_HEIGHT),
+            ..default()
+        },
+        background_color: Color::srgba(0., 0., 0., 0.5).into(),
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn((NodeBundle {
+            style: Style {
+                width: Val::Percent(0.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            background_color: Color::srgba(1., 1., 1., 0.7).into(),
+            ..default()
+        }, TimelineCursor));
+    });
+}
+
+/// Space : play/pause, up/down : speed, left/right : previous/next frame, click on the timeline : jump
+#[cfg(feature = "record")]
+fn replay_input(
+    mut replay: ResMut<BevyReplay>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    q_windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    let replay = &mut replay.0;
+    if keys.just_pressed(KeyCode::Space) {
+        replay.toggle_play();
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        replay.set_speed(replay.speed * 2.);
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        replay.set_speed(replay.speed / 2.);
+    }
+    if keys.just_pressed(KeyCode::ArrowRight) {
+        replay.step(1);
+    }
+    if keys.just_pressed(KeyCode::ArrowLeft) {
+        replay.step(-1);
+    }
+    if buttons.pressed(MouseButton::Left) {
+        let window = q_windows.single();
+        if let Some(position) = window.cursor_position() {
+            if position.y > window.height() - TIMELINE_HEIGHT {
+                replay.seek((position.x / window.width()) as f64);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "record")]
+fn advance_replay(
+    mut replay: ResMut<BevyReplay>,
+    time: Res<Time>,
+) {
+    replay.0.advance(time.delta_seconds_f64());
+}
+
+#[cfg(feature = "record")]
+fn move_replay_objects(
+    mut ball: Query<&mut Transform, With<Ball>>,
+    mut robots: Query<(&Robot, &mut Transform), Without<Ball>>,
+    replay: Res<BevyReplay>,
+    mut displayed: ResMut<DisplayedState>,
+    mut debug: ResMut<DisplayedDebug>,
+) {
+    let drawings = replay.0.debug_drawings();
+    if debug.0 != drawings {
+        debug.0 = drawings;
+    }
+    let gs = replay.0.current();
+    if let Some(ball_pos) = gs.ball {
+        *ball.single_mut() = Transform::from_xyz(ball_pos.x as f32, ball_pos.y as f32, 1.);
+    }
+    for (r, mut pos) in robots.iter_mut() {
+        *pos = robot_transform(gs.markers.get(*r));
+    }
+    displayed.0 = gs.clone();
+}
+
+#[cfg(feature = "record")]
+fn event_text(event: &RefereeEvent) -> String {
+    match event {
+        RefereeEvent::Goal(Team::Blue) => "Goal for blue".to_string(),
+        RefereeEvent::Goal(Team::Green) => "Goal for green".to_string(),
+        RefereeEvent::BallOut => "Ball out of field".to_string(),
+        RefereeEvent::Penalty(r, reason) => format!("{:?} penalized : {}", r, reason),
+        RefereeEvent::PenaltyEnd(r) => format!("End of the penalty of {:?}", r),
+        RefereeEvent::Unpenalized(r) => format!("{:?} unpenalized", r),
+        RefereeEvent::GoalCancelled(Team::Blue) => "Goal of blue cancelled".to_string(),
+        RefereeEvent::GoalCancelled(Team::Green) => "Goal of green cancelled".to_string(),
+        RefereeEvent::Start => "Game started".to_string(),
+        RefereeEvent::Pause => "Game paused".to_string(),
+        RefereeEvent::Resume => "Game resumed".to_string(),
+        RefereeEvent::Halftime => "Halftime".to_string(),
+        RefereeEvent::Shootout(team) => format!("Penalty shootout, {:?} kicks first", team),
+        RefereeEvent::ShootoutAttempt(team, true) => format!("{:?} scored its penalty", team),
+        RefereeEvent::ShootoutAttempt(team, false) => format!("{:?} missed its penalty", team),
+        RefereeEvent::ShootoutWinner(team) => format!("{:?} wins the penalty shootout", team),
+    }
+}
+
+#[cfg(feature = "record")]
+fn update_replay_ui(
+    replay: Res<BevyReplay>,
+    mut text: Query<&mut Text, With<ReplayText>>,
+    mut cursor: Query<&mut Style, With<TimelineCursor>>,
+) {
+    let replay = &replay.0;
+    let event = match replay.last_event() {
+        Some((t, event)) => format!("{} ({:.1} s ago)", event_text(event), (replay.t() - t) as f64 * DT),
+        None => String::new()
+    };
+    text.single_mut().sections[0].value = format!(
+        "{} x{}    {}",
+        if replay.playing {"Playing"} else {"Paused"}, replay.speed, event
+    );
+    cursor.single_mut().width = Val::Percent(replay.progress() as f32 * 100.);
 }
 
 pub struct BevyGUI;
 impl BevyGUI {
-    pub fn run(gc: GC) {
+    /// App with the window and the field
+    fn app() -> App {
         let mut app = App::new();
         app.add_plugins(DefaultPlugins
                 .set(WindowPlugin {
@@ -295,18 +1319,55 @@ impl BevyGUI {
                     ..default()
                 }).disable::<LogPlugin>()
             )
-            .insert_resource(Time::<Fixed>::from_seconds(DT as f64))
+            .add_systems(Startup, (setup, setup_hud, setup_debug_panel))
+            .add_systems(Update, (update_debug_panel, debug_toggles, draw_debug))
+            .add_systems(Update, (toggle_trails, update_trails, draw_trails).chain())
+            .add_systems(Update, camera_control)
+            .init_resource::<DisplayedState>()
+            .init_resource::<DisplayedDebug>()
+            .init_resource::<DebugVisibility>()
+            .init_resource::<Trails>()
+            .init_resource::<BallDamping>();
+        app
+    }
+    pub fn run(gc: GC) {
+        let mut app = Self::app();
+        app.insert_resource(Time::<Fixed>::from_seconds(DT as f64))
             .add_systems(FixedUpdate, step_simulation)
-            .add_systems(Startup, setup)
-            .add_systems(Update, move_objects)
-            .add_systems(Update, select_dragging)
-            .add_systems(Update, update_dragging)
-            .add_systems(Update, reset)
-            .add_systems(Update, kick)
-            .add_systems(Update, penalize)
+            .add_systems(Update, (move_objects, update_hud).chain())
+            .add_systems(Update, (update_dragging, select_dragging).chain().run_if(not_spectating))
+            .init_resource::<DragOrientation>()
+            .add_systems(Update, (reset, kick).run_if(not_spectating))
             // BevyGC and Dragging are NonSend on wasm so it's simpler if they always are
             .insert_non_send_resource(BevyGC(gc))
             .insert_non_send_resource(Dragging::default());
+        #[cfg(not(feature = "alternative_http_client"))]
+        app.add_systems(Startup, setup_referee_panel)
+            .add_systems(Update, update_live_debug.before(draw_debug).before(update_debug_panel))
+            .add_systems(Update, (referee_panel, select_panel_robot).run_if(not_spectating))
+            .add_systems(Update, update_referee_panel)
+            .add_systems(Update, flick_ball.after(update_dragging).before(select_dragging).run_if(not_spectating))
+            .init_resource::<Flick>()
+            .init_resource::<Aiming>()
+            .add_systems(Update, (toggle_manual_control, drive_manual_robot).chain().run_if(not_spectating))
+            .init_resource::<ManualControl>()
+            .insert_resource(RefereePanel {
+                selected: Robot::Blue1,
+                reason: 0
+            });
+
+        app.run();
+    }
+    /// Plays a recorded match instead of a live game controller
+    #[cfg(feature = "record")]
+    pub fn replay(replay: Replay) {
+        let mut app = Self::app();
+        if let Some(config) = &replay.config {
+            app.insert_resource(BallDamping(config.physics.ball_damping));
+        }
+        app.add_systems(Startup, setup_replay)
+            .add_systems(Update, (replay_input, advance_replay, move_replay_objects, update_hud, update_replay_ui).chain())
+            .insert_resource(BevyReplay(replay));
 
         app.run();
     }
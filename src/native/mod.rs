@@ -7,4 +7,12 @@ pub mod gui;
 pub mod control;
 
 #[cfg(feature = "native_gc")]
-pub mod gc;
\ No newline at end of file
+pub mod gc;
+#[cfg(all(feature = "native_gui", feature = "record"))]
+pub mod replay;
+
+#[cfg(feature = "record")]
+pub mod resimulation;
+
+#[cfg(all(feature = "native_gc", feature = "json"))]
+pub mod scenario;
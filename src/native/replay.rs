@@ -0,0 +1,117 @@
+//! Playback of a match recording (see `record`), independent of the GUI
+
+use crate::constants::DT;
+use crate::debug::{DebugDrawings, Layer};
+use crate::game_state::GameState;
+use crate::record::{RecordConfig, RecordEntry};
+use crate::referee::RefereeEvent;
+
+pub const MIN_SPEED: f64 = 1./16.;
+pub const MAX_SPEED: f64 = 16.;
+
+pub struct Replay {
+    pub config: Option<RecordConfig>,
+    /// (t, game state), ordered by t
+    pub frames: Vec<(usize, GameState)>,
+    /// (t, event), ordered by t
+    pub events: Vec<(usize, RefereeEvent)>,
+    /// (t, debug layer), ordered by t
+    pub debug: Vec<(usize, Layer)>,
+    /// Current time in frames. Between two recorded frames while playing
+    t: f64,
+    pub playing: bool,
+    /// 1. is real time
+    pub speed: f64,
+}
+impl Replay {
+    /// Returns None if the recording contains no game state
+    pub fn new(entries: Vec<RecordEntry>) -> Option<Self> {
+        let mut config = None;
+        let mut frames = Vec::new();
+        let mut events = Vec::new();
+        let mut debug = Vec::new();
+        for entry in entries {
+            match entry {
+                RecordEntry::Config(c) => config = Some(c),
+                RecordEntry::Frame { t, state } => frames.push((t, state)),
+                RecordEntry::Event { t, event } => events.push((t, event)),
+                RecordEntry::Debug { t, layer } => debug.push((t, layer)),
+                RecordEntry::Command { .. } | RecordEntry::Intervention { .. } | RecordEntry::Drive { .. } => ()
+            }
+        }
+        let t = frames.first()?.0 as f64;
+        Some(Self {
+            config,
+            frames,
+            events,
+            debug,
+            t,
+            playing: true,
+            speed: 1.
+        })
+    }
+    pub fn start(&self) -> usize {
+        self.frames[0].0
+    }
+    pub fn end(&self) -> usize {
+        self.frames[self.frames.len() - 1].0
+    }
+    /// Index of the last frame before the current time
+    pub fn index(&self) -> usize {
+        self.frames.partition_point(|(t, _)| *t as f64 <= self.t).max(1) - 1
+    }
+    pub fn current(&self) -> &GameState {
+        &self.frames[self.index()].1
+    }
+    /// Current time in frames
+    pub fn t(&self) -> usize {
+        self.t as usize
+    }
+    /// Position in the recording between 0. and 1.
+    pub fn progress(&self) -> f64 {
+        (self.t - self.start() as f64) / (self.end() - self.start()).max(1) as f64
+    }
+    /// Advances of `dt` real seconds if playing. Stops at the end
+    pub fn advance(&mut self, dt: f64) {
+        if !self.playing {
+            return
+        }
+        self.t += dt * self.speed / DT;
+        if self.t >= self.end() as f64 {
+            self.t = self.end() as f64;
+            self.playing = false;
+        }
+    }
+    /// Jumps to a position between 0. and 1.
+    pub fn seek(&mut self, progress: f64) {
+        self.t = self.start() as f64 + progress.clamp(0., 1.) * (self.end() - self.start()) as f64;
+    }
+    /// Moves of `n` recorded frames and pauses
+    pub fn step(&mut self, n: isize) {
+        self.playing = false;
+        let i = (self.index() as isize + n).clamp(0, self.frames.len() as isize - 1) as usize;
+        self.t = self.frames[i].0 as f64;
+    }
+    pub fn toggle_play(&mut self) {
+        if !self.playing && self.t() >= self.end() {
+            self.t = self.start() as f64;
+        }
+        self.playing = !self.playing;
+    }
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+    /// Debug drawings at the current time
+    pub fn debug_drawings(&self) -> DebugDrawings {
+        let mut drawings = DebugDrawings::default();
+        let end = self.debug.partition_point(|(t, _)| *t as f64 <= self.t);
+        for (_, layer) in self.debug[..end].iter() {
+            drawings.set(layer.clone());
+        }
+        drawings
+    }
+    /// Last referee event before the current time
+    pub fn last_event(&self) -> Option<&(usize, RefereeEvent)> {
+        self.events.iter().take_while(|(t, _)| *t as f64 <= self.t).last()
+    }
+}
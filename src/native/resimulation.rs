@@ -0,0 +1,251 @@
+//! Re-runs the physics of a match recording (see `record`) from its first game state, its commands and its interventions
+//! (robots moved in the GUI, referee decisions, scenarios...).
+//! The physical parameters or the commands of a team can be changed to see how the match would have diverged.
+//!
+//! The re-simulation is exact for the recordings started at the creation of the game controller.
+//! Otherwise it starts from the recorded positions (and velocities with `--extended-state`) and the scores start at 0.
+
+use tracing::warn;
+
+use crate::constants::simu::MULTIPLIER;
+use crate::game_state::{GameState, Robot, Team};
+use crate::native::gc::Intervention;
+use crate::record::RecordEntry;
+use crate::simulation::PhysicsParams;
+use crate::strategy::Command;
+use crate::GC;
+
+/// Preemption reason of the robots driven by hand in the recording
+const RECORDED_DRIVE: &str = "Recorded manual control";
+
+/// Recorded action that is not a command of a team
+enum Action {
+    Intervention(Intervention),
+    /// (robot, control, kick), see `RecordEntry::Drive`
+    Drive(Robot, Option<(f32, f32, f32)>, Option<f32>),
+}
+
+pub struct Resimulation {
+    pub gc: GC,
+    /// (t, command) ordered by t, with t relative to the first game state of the recording
+    commands: Vec<(usize, Command)>,
+    /// Index of the next command to apply
+    next: usize,
+    /// (t, action) ordered by t, applied before the commands of their frame
+    actions: Vec<(usize, Action)>,
+    /// Index of the next action to apply
+    next_action: usize,
+    /// Last frame of the recording, relative to the first game state
+    end: usize,
+}
+impl Resimulation {
+    /// Returns None if the recording contains no game state
+    pub fn new(entries: &[RecordEntry]) -> Option<Self> {
+        let (start, first) = entries.iter().find_map(|e| match e {
+            RecordEntry::Frame { t, state } => Some((*t, state)),
+            _ => None
+        })?;
+        let end = entries.iter().filter_map(|e| match e {
+            RecordEntry::Frame { t, .. } => Some(*t),
+            _ => None
+        }).max()? - start;
+
+        let mut gc = match entries.iter().find_map(|e| match e {
+            RecordEntry::Config(c) => Some(c),
+            _ => None
+        }) {
+            Some(config) => {
+                let mut gc = GC::headless(config.blue_team_name.clone(), config.green_team_name.clone(), "".to_string(), "".to_string(), config.blue_team_positive);
+                gc.set_physics_params(config.physics.clone());
+                gc
+            },
+            None => GC::headless("".to_string(), "".to_string(), "".to_string(), "".to_string(), false)
+        };
+        if start != 0 {
+            warn!("The recording doesn't start at the beginning of the match, the re-simulation is approximate");
+            restore(&mut gc, first);
+        }
+        Some(Self {
+            gc,
+            commands: commands(entries, start),
+            next: 0,
+            actions: actions(entries, start),
+            next_action: 0,
+            end
+        })
+    }
+    /// Replaces the physical parameters, also the ones set during the recording (for example by a scenario)
+    pub fn set_physics_params(&mut self, params: PhysicsParams) {
+        self.actions.retain(|(_, action)| !matches!(action, Action::Intervention(Intervention::Physics(_))));
+        self.next_action = self.actions.partition_point(|(t, _)| *t < self.gc.simu.t);
+        self.gc.set_physics_params(params);
+    }
+    /// Removes the recorded commands of `team`, for example to let a strategy play instead (see `GC::set_strategy`)
+    pub fn remove_commands(&mut self, team: Team) {
+        self.commands.retain(|(_, cmd)| command_robot(cmd).team() != team);
+        self.next = self.commands.partition_point(|(t, _)| *t < self.gc.simu.t);
+    }
+    /// Uses the commands of `team` from another recording. Its time is counted from its first game state
+    pub fn replace_commands(&mut self, team: Team, entries: &[RecordEntry]) {
+        let start = entries.iter().find_map(|e| match e {
+            RecordEntry::Frame { t, .. } => Some(*t),
+            _ => None
+        }).unwrap_or(0);
+        self.remove_commands(team);
+        self.commands.extend(commands(entries, start).into_iter().filter(|(_, cmd)| command_robot(cmd).team() == team));
+        // Stable sort : the order of the commands of a frame is kept
+        self.commands.sort_by_key(|(t, _)| *t);
+        self.next = self.commands.partition_point(|(t, _)| *t < self.gc.simu.t);
+    }
+    /// True when the end of the recording is reached
+    pub fn finished(&self) -> bool {
+        self.gc.simu.t >= self.end
+    }
+    /// Applies the interventions and the commands of the current frame and steps the game controller
+    pub fn step(&mut self) {
+        while let Some((t, action)) = self.actions.get(self.next_action) {
+            if *t > self.gc.simu.t {
+                break
+            }
+            match action {
+                Action::Intervention(intervention) => {
+                    self.gc.intervene(intervention.clone());
+                },
+                Action::Drive(r, control, kick) => {
+                    let mut tasks = self.gc.referee.lock_tasks_mut();
+                    let task = &mut tasks[*r as usize];
+                    match control {
+                        Some(control) => {
+                            task.driven = Some(RECORDED_DRIVE);
+                            task.control = *control;
+                            if kick.is_some() {
+                                task.kick = *kick;
+                            }
+                        },
+                        // Unless the referee or a scenario took the robot in the meantime
+                        None => if task.driven == Some(RECORDED_DRIVE) {
+                            task.driven = None;
+                            task.control = (0., 0., 0.);
+                        }
+                    }
+                }
+            }
+            self.next_action += 1;
+        }
+        {
+            let mut tasks = self.gc.referee.lock_tasks_mut();
+            while let Some((t, cmd)) = self.commands.get(self.next) {
+                if *t > self.gc.simu.t {
+                    break
+                }
+                match *cmd {
+                    Command::Control(r, control) => tasks[r as usize].control = control,
+                    Command::Kick(r, f) => tasks[r as usize].kick = Some(f)
+                }
+                self.next += 1;
+            }
+        }
+        self.gc.step();
+    }
+    /// Runs until the end of the recording
+    pub fn run(&mut self) {
+        while !self.finished() {
+            self.step();
+        }
+    }
+}
+
+/// Commands of a recording with their time relative to `start`
+fn commands(entries: &[RecordEntry], start: usize) -> Vec<(usize, Command)> {
+    entries.iter().filter_map(|e| match e {
+        RecordEntry::Command { t, command, .. } if *t >= start => Some((t - start, *command)),
+        _ => None
+    }).collect()
+}
+
+/// Interventions and controls of the robots driven by hand of a recording, with their time relative to `start`
+fn actions(entries: &[RecordEntry], start: usize) -> Vec<(usize, Action)> {
+    entries.iter().filter_map(|e| match e {
+        RecordEntry::Intervention { t, intervention } if *t >= start => Some((t - start, Action::Intervention(intervention.clone()))),
+        RecordEntry::Drive { t, robot, control, kick } if *t >= start => Some((t - start, Action::Drive(*robot, *control, *kick))),
+        _ => None
+    }).collect()
+}
+
+fn command_robot(cmd: &Command) -> Robot {
+    match *cmd {
+        Command::Control(r, _) | Command::Kick(r, _) => r
+    }
+}
+
+/// Puts the ball and the robots at their recorded positions and velocities
+fn restore(gc: &mut GC, gs: &GameState) {
+    if let Some(ball) = gs.ball {
+        gc.teleport_entity(gc.get_ball_handle(), ball, None);
+    }
+    for r in Robot::all() {
+        let pose = gs.markers.get(r);
+        gc.teleport_entity(gc.get_robot_handle(r), pose.position, Some(pose.orientation));
+    }
+    if let Some(extended) = &gs.extended {
+        let ball = gc.get_ball_handle();
+        gc.simu.bodies[ball].set_linvel(extended.ball_velocity*MULTIPLIER, true);
+        for r in Robot::all() {
+            let v = extended.velocities.get(r);
+            let handle = gc.get_robot_handle(r);
+            let body = &mut gc.simu.bodies[handle];
+            body.set_linvel(v.linear*MULTIPLIER, true);
+            body.set_angvel(v.angular, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::record;
+    use crate::strategy;
+
+    #[test]
+    fn resimulation_reproduces_the_recorded_frames() {
+        let path = std::env::temp_dir().join(format!("rsk_resimulation_{}", std::process::id()));
+        let mut gc = GC::headless("Blue".to_string(), "Green".to_string(), String::new(), String::new(), false);
+        gc.set_strategy(Team::Blue, strategy::by_name("baseline"));
+        gc.set_strategy(Team::Green, strategy::by_name("chaser"));
+        gc.start_recording(&path).unwrap();
+        gc.intervene(Intervention::Start);
+        for t in 0..3000 {
+            if t == 1500 {
+                gc.teleport_entity(gc.get_ball_handle(), Point2::new(0.2, 0.3), None);
+            }
+            gc.step();
+        }
+        gc.stop_recording();
+        let entries = record::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let frames: Vec<(usize, GameState)> = entries.iter().filter_map(|e| match e {
+            RecordEntry::Frame { t, state } => Some((*t, state.clone())),
+            _ => None
+        }).collect();
+        assert!(frames.len() > 2);
+        let mut resimulation = Resimulation::new(&entries).unwrap();
+        let mut compared = 1;
+        while !resimulation.finished() {
+            resimulation.step();
+            let Some((_, recorded)) = frames.iter().find(|(t, _)| *t == resimulation.gc.simu.t) else {
+                continue
+            };
+            let gs = resimulation.gc.get_game_state();
+            assert_eq!(gs.ball, recorded.ball, "ball at t = {}", resimulation.gc.simu.t);
+            for r in Robot::all() {
+                let (pose, expected) = (gs.markers.get(r), recorded.markers.get(r));
+                assert_eq!((pose.position, pose.orientation), (expected.position, expected.orientation), "{:?} at t = {}", r, resimulation.gc.simu.t);
+            }
+            compared += 1;
+        }
+        assert_eq!(compared, frames.len());
+    }
+}
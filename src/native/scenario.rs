@@ -0,0 +1,342 @@
+//! Scenarios : repeatable drills described in json files, for example "defend a 2v1 counterattack".
+//! A scenario gives the initial poses and velocities, scripts robots with waypoints, chooses the active
+//! rules and decides if the drill passes or fails. The robots that are not listed are parked outside of the field.
+//!
+//! ```json
+//! {
+//!     "name": "Defend a 2v1 counterattack",
+//!     "duration": 10,
+//!     "ball": {"position": [0.3, 0.1], "velocity": [-0.5, 0]},
+//!     "robots": {
+//!         "blue1": {"position": [-0.8, 0], "orientation": 0},
+//!         "green1": {"position": [0.4, 0.2], "orientation": 3.14, "waypoints": [{"position": [-0.3, 0.2], "orientation": 3.14}]},
+//!         "green2": {"position": [0.5, -0.2], "orientation": 3.14}
+//!     },
+//!     "rules": {"ball_abuse": false},
+//!     "failure": [{"goal": "Green"}, {"penalty": "Blue"}],
+//!     "timeout": "pass"
+//! }
+//! ```
+
+use std::io;
+use std::path::Path;
+
+use nalgebra::{Point2, Vector2};
+use tracing::info;
+
+use crate::constants::real::PARKING_SPOTS;
+use crate::constants::{simu::MULTIPLIER, DT, real::{FIELD, GOAL_HEIGHT}};
+use crate::game_state::{GameState, Pose, Robot, Team};
+use crate::referee::{RefereeEvent, Rules};
+use crate::simulation::PhysicsParams;
+use crate::GC;
+
+/// Preemption reason of the scripted and parked robots
+pub(crate) const SCRIPTED: &str = "Scenario";
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct BallSetup {
+    pub position: Point2<f64>,
+    /// In m/s
+    #[serde(default)]
+    pub velocity: Vector2<f64>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RobotSetup {
+    pub position: Point2<f64>,
+    #[serde(default)]
+    pub orientation: f64,
+    /// In m/s
+    #[serde(default)]
+    pub velocity: Vector2<f64>,
+    /// Poses reached one after the other by a scripted robot. The controllers and strategies can't drive it
+    #[serde(default)]
+    pub waypoints: Vec<Pose>,
+}
+
+/// None for the parked robots
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RobotSetups {
+    pub blue1: Option<RobotSetup>,
+    pub blue2: Option<RobotSetup>,
+    pub green1: Option<RobotSetup>,
+    pub green2: Option<RobotSetup>,
+}
+impl RobotSetups {
+    pub fn get(&self, r: Robot) -> Option<&RobotSetup> {
+        match r {
+            Robot::Blue1 => self.blue1.as_ref(),
+            Robot::Blue2 => self.blue2.as_ref(),
+            Robot::Green1 => self.green1.as_ref(),
+            Robot::Green2 => self.green2.as_ref(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// The team scored (the ball crossed the goal line of its opponent, even if the goals rule is disabled)
+    Goal(Team),
+    /// A robot of the team was penalized
+    Penalty(Team),
+    /// The ball left the field, elsewhere than in a goal
+    BallOut,
+    /// The ball is in the rectangle
+    BallIn {
+        min: Point2<f64>,
+        max: Point2<f64>,
+    },
+}
+
+impl Condition {
+    /// True if the condition is met by the game state and the referee events of the last step
+    pub fn happened(&self, gs: &GameState, events: &[RefereeEvent]) -> bool {
+        match self {
+            Condition::Goal(team) => events.iter().any(|e| matches!(e, RefereeEvent::Goal(t) if t == team))
+                || gs.ball.is_some_and(|b| b.y.abs() < GOAL_HEIGHT/2. && b.x * team.opponent().goal(&gs.referee).x.signum() > FIELD.0/2.),
+            Condition::Penalty(team) => events.iter().any(|e| matches!(e, RefereeEvent::Penalty(r, _) if r.team() == *team)),
+            Condition::BallOut => events.iter().any(|e| matches!(e, RefereeEvent::BallOut))
+                || gs.ball.is_some_and(|b| b.y.abs() > FIELD.1/2. || (b.x.abs() > FIELD.0/2. && b.y.abs() >= GOAL_HEIGHT/2.)),
+            Condition::BallIn { min, max } => gs.ball.is_some_and(|b| b.x >= min.x && b.x <= max.x && b.y >= min.y && b.y <= max.y),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Pass,
+    #[default]
+    Fail,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    /// Time limit in seconds
+    pub duration: f64,
+    pub ball: BallSetup,
+    #[serde(default)]
+    pub robots: RobotSetups,
+    #[serde(default)]
+    pub rules: Rules,
+    /// The scenario passes as soon as one of them happens
+    #[serde(default)]
+    pub success: Vec<Condition>,
+    /// The scenario fails as soon as one of them happens. Checked before the success conditions
+    #[serde(default)]
+    pub failure: Vec<Condition>,
+    /// Outcome when the time limit is reached
+    #[serde(default)]
+    pub timeout: Outcome,
+    /// The current parameters are kept if None
+    #[serde(default)]
+    pub physics: Option<PhysicsParams>,
+}
+impl Scenario {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let json = std::fs::read(path)?;
+        serde_json::from_slice(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScenarioResult {
+    pub passed: bool,
+    /// Condition that ended the scenario
+    pub reason: String,
+    /// Seconds since the start of the scenario
+    pub time: f64,
+}
+
+pub struct ScenarioRunner {
+    pub scenario: Scenario,
+    /// Frame of the start
+    start: usize,
+    /// Index of the current waypoint of each robot
+    waypoints: [usize; 4],
+    pub result: Option<ScenarioResult>,
+}
+impl ScenarioRunner {
+    pub fn new(scenario: Scenario) -> Self {
+        Self {
+            scenario,
+            start: 0,
+            waypoints: [0; 4],
+            result: None
+        }
+    }
+    /// Starts a new match in the initial situation of the scenario
+    pub fn setup(&mut self, gc: &mut GC) {
+        let scenario = &self.scenario;
+        gc.reset_match();
+        if let Some(physics) = &scenario.physics {
+            gc.set_physics_params(physics.clone());
+        }
+        gc.referee.rules = scenario.rules;
+        let ball = gc.get_ball_handle();
+        gc.teleport_entity(ball, scenario.ball.position, None);
+        gc.set_velocity(ball, scenario.ball.velocity);
+        for r in Robot::all() {
+            let handle = gc.get_robot_handle(r);
+            match scenario.robots.get(r) {
+                Some(setup) => {
+                    gc.teleport_entity(handle, setup.position, Some(setup.orientation));
+                    gc.set_velocity(handle, setup.velocity);
+                },
+                None => gc.teleport_entity(handle, PARKING_SPOTS[r as usize], Some(0.))
+            }
+        }
+        let mut tasks = gc.referee.lock_tasks_mut();
+        for r in Robot::all() {
+            tasks[r as usize].driven = match scenario.robots.get(r) {
+                Some(setup) if setup.waypoints.is_empty() => None,
+                _ => Some(SCRIPTED)
+            };
+        }
+        drop(tasks);
+        self.start = gc.simu.t;
+        self.waypoints = [0; 4];
+        self.result = None;
+        info!("Scenario {} started", scenario.name);
+    }
+    /// Drives the scripted robots and holds the parked ones, before the step of the game controller
+    pub fn drive(&mut self, gc: &mut GC) {
+        if self.result.is_some() {
+            return
+        }
+        for r in Robot::all() {
+            if self.scenario.robots.get(r).is_none() {
+                let handle = gc.get_robot_handle(r);
+                gc.teleport_entity(handle, PARKING_SPOTS[r as usize], Some(0.));
+            }
+        }
+        let mut tasks = gc.referee.lock_tasks_mut();
+        for r in Robot::all() {
+            let waypoints = match self.scenario.robots.get(r) {
+                Some(setup) if !setup.waypoints.is_empty() => &setup.waypoints,
+                _ => continue
+            };
+            let body = &gc.simu.bodies[gc.simu.get_robot_handle(r)];
+            let pose = Pose {
+                position: Point2::from(body.translation() / MULTIPLIER),
+                orientation: body.rotation().angle()
+            };
+            let i = &mut self.waypoints[r as usize];
+            let (arrived, control) = pose.goto(&waypoints[*i]);
+            if arrived && *i + 1 < waypoints.len() {
+                *i += 1;
+            }
+            // Stays on the last waypoint
+            tasks[r as usize].control = control;
+        }
+    }
+    /// Checks the conditions after the step of the game controller
+    pub fn check(&mut self, gc: &mut GC) {
+        if self.result.is_some() {
+            return
+        }
+        let time = (gc.simu.t - self.start) as f64 * DT;
+        let gs = gc.get_game_state();
+        let happened = |condition: &Condition| condition.happened(&gs, &gc.last_events);
+        let result = if let Some(c) = self.scenario.failure.iter().find(|c| happened(c)) {
+            Some((false, format!("{:?}", c)))
+        } else if let Some(c) = self.scenario.success.iter().find(|c| happened(c)) {
+            Some((true, format!("{:?}", c)))
+        } else if time >= self.scenario.duration {
+            Some((self.scenario.timeout == Outcome::Pass, "Time limit".to_string()))
+        } else {
+            None
+        };
+        if let Some((passed, reason)) = result {
+            info!("Scenario {} {} after {:.2} s : {}", self.scenario.name, if passed {"passed"} else {"failed"}, time, reason);
+            self.result = Some(ScenarioResult { passed, reason, time });
+            // Everything stops, for the GUI
+            gc.referee.pause(gc.simu.t);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Blue defends the negative x, like in a headless game controller with `blue_team_positive` false
+    fn state(ball: (f64, f64)) -> GameState {
+        let mut gs = GameState::default();
+        gs.ball = Some(Point2::new(ball.0, ball.1));
+        gs.referee.teams.blue.x_positive = false;
+        gs.referee.teams.green.x_positive = true;
+        gs
+    }
+
+    fn run(gc: &mut GC) -> ScenarioResult {
+        for _ in 0..100_000 {
+            if let Some(result) = gc.scenario_result() {
+                return result.clone()
+            }
+            gc.step();
+        }
+        panic!("The scenario didn't end")
+    }
+
+    #[test]
+    fn goal_is_scored_in_the_goal_of_the_opponent() {
+        let gs = state((FIELD.0/2. + 0.02, 0.));
+        assert!(Condition::Goal(Team::Blue).happened(&gs, &[]));
+        assert!(!Condition::Goal(Team::Green).happened(&gs, &[]));
+        assert!(!Condition::BallOut.happened(&gs, &[]));
+    }
+
+    #[test]
+    fn goal_is_taken_from_the_referee_events() {
+        let gs = state((0., 0.));
+        let events = [RefereeEvent::Goal(Team::Green)];
+        assert!(Condition::Goal(Team::Green).happened(&gs, &events));
+        assert!(!Condition::Goal(Team::Blue).happened(&gs, &events));
+    }
+
+    #[test]
+    fn ball_out_is_not_a_goal() {
+        // Behind the goal line, beside the goal
+        let gs = state((FIELD.0/2. + 0.02, GOAL_HEIGHT/2. + 0.05));
+        assert!(Condition::BallOut.happened(&gs, &[]));
+        assert!(!Condition::Goal(Team::Blue).happened(&gs, &[]));
+        // Over a side line
+        assert!(Condition::BallOut.happened(&state((0., FIELD.1/2. + 0.02)), &[]));
+        assert!(Condition::BallOut.happened(&state((0., 0.)), &[RefereeEvent::BallOut]));
+        assert!(!Condition::BallOut.happened(&state((0.5, 0.5)), &[]));
+    }
+
+    #[test]
+    fn ball_in_checks_the_rectangle() {
+        let condition = Condition::BallIn { min: Point2::new(0., -0.2), max: Point2::new(0.4, 0.2) };
+        assert!(condition.happened(&state((0.2, 0.1)), &[]));
+        assert!(!condition.happened(&state((-0.1, 0.1)), &[]));
+        assert!(!condition.happened(&state((0.2, 0.3)), &[]));
+    }
+
+    #[test]
+    fn empty_goal_scenario_passes() {
+        let scenario = Scenario::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios/empty_goal.json")).unwrap();
+        let mut gc = GC::headless(String::new(), String::new(), String::new(), String::new(), false);
+        gc.start_scenario(scenario);
+        let result = run(&mut gc);
+        assert!(result.passed, "{}", result.reason);
+    }
+
+    #[test]
+    fn scenario_fails_at_the_time_limit() {
+        let mut scenario = Scenario::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios/empty_goal.json")).unwrap();
+        scenario.ball.velocity = Vector2::zeros();
+        scenario.duration = 0.5;
+        let mut gc = GC::headless(String::new(), String::new(), String::new(), String::new(), false);
+        gc.start_scenario(scenario);
+        let result = run(&mut gc);
+        assert!(!result.passed);
+        assert_eq!(result.reason, "Time limit");
+    }
+}
@@ -0,0 +1,227 @@
+//! Python bindings of the headless game controller and of the reinforcement learning environments.
+//! Built with maturin (see pyproject.toml) :
+//! ```python
+//! import rsk_simulation
+//! gc = rsk_simulation.GC()
+//! gc.control("blue1", 0.2, 0., 0.)
+//! gc.step(1000)
+//! print(gc.game_state()["markers"]["blue1"])
+//! ```
+
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyArrayMethods, PyReadonlyArray1, PyReadonlyArray2};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::constants::real::DEFAULT_BALL_POS;
+use crate::game_state::{Robot, Team};
+use crate::rl::{Env, EnvConfig, Info, Randomization, VecEnv, ACTION_SIZE};
+use crate::strategy;
+use crate::GC;
+
+/// Ball (x, y, vx, vy) and robots (x, y, orientation, vx, vy, angular velocity)
+const SNAPSHOT_SIZE: usize = 4 + 6*4;
+
+fn robot(name: &str) -> PyResult<Robot> {
+    Robot::from_name(name).ok_or_else(|| PyValueError::new_err(format!("Unknown robot : {}", name)))
+}
+
+fn team(name: &str) -> PyResult<Team> {
+    match name {
+        "blue" => Ok(Team::Blue),
+        "green" => Ok(Team::Green),
+        _ => Err(PyValueError::new_err(format!("Unknown team : {}", name)))
+    }
+}
+
+fn env_config(team_name: &str, opponent: Option<&str>, frame_skip: Option<usize>, max_steps: Option<usize>, random_start: bool, randomize_physics: bool) -> PyResult<EnvConfig> {
+    let default = EnvConfig::default();
+    Ok(EnvConfig {
+        team: team(team_name)?,
+        opponent: match opponent {
+            Some(name) => Some(strategy::by_name(name).ok_or_else(|| PyValueError::new_err(format!("Unknown strategy : {}", name)))?),
+            None => None
+        },
+        frame_skip: frame_skip.unwrap_or(default.frame_skip),
+        max_steps: max_steps.unwrap_or(default.max_steps),
+        random_start,
+        randomization: randomize_physics.then(Randomization::default),
+        ..default
+    })
+}
+
+fn info_dict<'py>(py: Python<'py>, info: &Info) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("t", info.t)?;
+    dict.set_item("goal", info.goal.map(|t| match t {
+        Team::Blue => "blue",
+        Team::Green => "green"
+    }))?;
+    Ok(dict)
+}
+
+/// Game controller without sockets nor GUI
+#[pyclass(name = "GC")]
+struct PyGC(GC);
+
+#[pymethods]
+impl PyGC {
+    #[new]
+    #[pyo3(signature = (blue_team_positive = false))]
+    fn new(blue_team_positive: bool) -> Self {
+        let mut gc = GC::headless("".to_string(), "".to_string(), "".to_string(), "".to_string(), blue_team_positive);
+        gc.set_extended_state(true);
+        Self(gc)
+    }
+    /// Advances the simulation of `n` frames (of 1 ms)
+    #[pyo3(signature = (n = 1))]
+    fn step(&mut self, py: Python<'_>, n: usize) {
+        let gc = &mut self.0;
+        py.allow_threads(|| {
+            for _ in 0..n {
+                gc.step();
+            }
+        });
+    }
+    /// Starts a new match
+    fn reset(&mut self) {
+        self.0.reset_match();
+    }
+    /// Frame of the simulation
+    #[getter]
+    fn t(&self) -> usize {
+        self.0.simu.t
+    }
+    /// Game state as a dict, like the json of the game controller
+    fn game_state<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let json = String::from_utf8(self.0.get_game_state().to_json()).unwrap();
+        py.import_bound("json")?.call_method1("loads", (json,))
+    }
+    /// Positions and velocities as a numpy array :
+    /// [ball x, ball y, ball vx, ball vy] then [x, y, orientation, vx, vy, angular velocity] for blue1, blue2, green1 and green2
+    fn snapshot<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        let gs = self.0.get_game_state();
+        let extended = gs.extended.unwrap();
+        let ball = gs.ball.unwrap_or(DEFAULT_BALL_POS);
+        let mut values = Vec::with_capacity(SNAPSHOT_SIZE);
+        values.extend([ball.x, ball.y, extended.ball_velocity.x, extended.ball_velocity.y]);
+        for r in Robot::all() {
+            let pose = gs.markers.get(r);
+            let v = extended.velocities.get(r);
+            values.extend([pose.position.x, pose.position.y, pose.orientation, v.linear.x, v.linear.y, v.angular]);
+        }
+        values.into_pyarray_bound(py)
+    }
+    fn teleport_ball(&mut self, x: f64, y: f64) {
+        self.0.teleport_entity(self.0.get_ball_handle(), [x, y].into(), None);
+    }
+    #[pyo3(signature = (robot_name, x, y, orientation = None))]
+    fn teleport_robot(&mut self, robot_name: &str, x: f64, y: f64, orientation: Option<f64>) -> PyResult<()> {
+        let handle = self.0.get_robot_handle(robot(robot_name)?);
+        self.0.teleport_entity(handle, [x, y].into(), orientation);
+        Ok(())
+    }
+    /// Speeds in the robot's frame (m/s, m/s, rad/s)
+    fn control(&mut self, robot_name: &str, x: f32, y: f32, r: f32) -> PyResult<()> {
+        let r_id = robot(robot_name)?;
+        self.0.referee.lock_tasks_mut()[r_id as usize].control = (x, y, r);
+        Ok(())
+    }
+    /// power between 0. and 1.
+    fn kick(&mut self, robot_name: &str, power: f32) -> PyResult<()> {
+        let r = robot(robot_name)?;
+        self.0.referee.lock_tasks_mut()[r as usize].kick = Some(power);
+        Ok(())
+    }
+}
+
+/// Gymnasium-like environment
+#[pyclass(name = "Env")]
+struct PyEnv(Env);
+
+#[pymethods]
+impl PyEnv {
+    #[new]
+    #[pyo3(signature = (team = "blue", opponent = None, frame_skip = None, max_steps = None, random_start = true, randomize_physics = false))]
+    fn new(team: &str, opponent: Option<&str>, frame_skip: Option<usize>, max_steps: Option<usize>, random_start: bool, randomize_physics: bool) -> PyResult<Self> {
+        Ok(Self(Env::new(env_config(team, opponent, frame_skip, max_steps, random_start, randomize_physics)?)))
+    }
+    #[getter]
+    fn observation_size(&self) -> usize {
+        self.0.observation_size()
+    }
+    #[getter]
+    fn action_size(&self) -> usize {
+        ACTION_SIZE
+    }
+    #[pyo3(signature = (seed = None))]
+    fn reset<'py>(&mut self, py: Python<'py>, seed: Option<u64>) -> Bound<'py, PyArray1<f32>> {
+        self.0.reset(seed).into_pyarray_bound(py)
+    }
+    /// Returns (observation, reward, terminated, truncated, info)
+    fn step<'py>(&mut self, py: Python<'py>, actions: PyReadonlyArray1<'py, f32>) -> PyResult<(Bound<'py, PyArray1<f32>>, f64, bool, bool, Bound<'py, PyDict>)> {
+        let (obs, reward, terminated, truncated, info) = self.0.step(actions.as_slice()?)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok((obs.into_pyarray_bound(py), reward, terminated, truncated, info_dict(py, &info)?))
+    }
+}
+
+/// Many environments stepped in parallel. Observations are arrays of shape (num_envs, observation_size)
+#[pyclass(name = "VecEnv")]
+struct PyVecEnv(VecEnv);
+
+#[pymethods]
+impl PyVecEnv {
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (num_envs, threads = None, team = "blue", opponent = None, frame_skip = None, max_steps = None, random_start = true, randomize_physics = false))]
+    fn new(num_envs: usize, threads: Option<usize>, team: &str, opponent: Option<&str>, frame_skip: Option<usize>, max_steps: Option<usize>, random_start: bool, randomize_physics: bool) -> PyResult<Self> {
+        // Checks the arguments before spawning the threads
+        env_config(team, opponent, frame_skip, max_steps, random_start, randomize_physics)?;
+        Ok(Self(VecEnv::new(num_envs, threads, |_| env_config(team, opponent, frame_skip, max_steps, random_start, randomize_physics).unwrap())))
+    }
+    #[getter]
+    fn num_envs(&self) -> usize {
+        self.0.num_envs()
+    }
+    #[getter]
+    fn observation_size(&self) -> usize {
+        self.0.observation_size()
+    }
+    #[getter]
+    fn action_size(&self) -> usize {
+        ACTION_SIZE
+    }
+    fn observations<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        PyArray1::from_slice_bound(py, &self.0.observations).reshape([self.0.num_envs(), self.0.observation_size()])
+    }
+    #[pyo3(signature = (seed = None))]
+    fn reset<'py>(&mut self, py: Python<'py>, seed: Option<u64>) -> PyResult<Bound<'py, PyArray2<f32>>> {
+        let envs = &mut self.0;
+        py.allow_threads(|| {
+            envs.reset(seed);
+        });
+        self.observations(py)
+    }
+    /// `actions` has a shape of (num_envs, action_size). Returns (observations, rewards, terminated, truncated).
+    /// Finished environments are reset automatically
+    fn step<'py>(&mut self, py: Python<'py>, actions: PyReadonlyArray2<'py, f32>) -> PyResult<(Bound<'py, PyArray2<f32>>, Bound<'py, PyArray1<f64>>, Bound<'py, PyArray1<bool>>, Bound<'py, PyArray1<bool>>)> {
+        let actions = actions.as_slice()?;
+        let envs = &mut self.0;
+        py.allow_threads(|| envs.step(actions)).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok((
+            self.observations(py)?,
+            PyArray1::from_slice_bound(py, &self.0.rewards),
+            PyArray1::from_slice_bound(py, &self.0.terminated),
+            PyArray1::from_slice_bound(py, &self.0.truncated)
+        ))
+    }
+}
+
+#[pymodule]
+fn rsk_simulation(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyGC>()?;
+    m.add_class::<PyEnv>()?;
+    m.add_class::<PyVecEnv>()?;
+    Ok(())
+}
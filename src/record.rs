@@ -0,0 +1,252 @@
+//! Match recordings. Everything is written incrementally to the file : the configuration, the game states,
+//! the commands received, the interventions (GUI, scripts, scenarios), the referee events and the debug drawings.
+//!
+//! Files ending with `.jsonl` contain one json entry per line, the other ones contain bitcode entries prefixed by their length (u32, little endian).
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+use tracing::warn;
+
+use crate::debug::Layer;
+use crate::game_state::{GameState, Robot};
+use crate::native::gc::Intervention;
+use crate::referee::RefereeEvent;
+use crate::simulation::PhysicsParams;
+use crate::strategy::Command;
+
+/// Parameters of the recorded match
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct RecordConfig {
+    pub blue_team_name: String,
+    pub green_team_name: String,
+    pub blue_team_positive: bool,
+    pub physics: PhysicsParams,
+    /// Number of frames between two recorded game states
+    pub frame_period: usize,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum RecordEntry {
+    /// Always the first entry
+    Config(RecordConfig),
+    /// Game state at the frame `t`
+    Frame {
+        t: usize,
+        state: GameState,
+    },
+    /// Command applied at the frame `t`. The key is empty for the built-in strategies
+    Command {
+        t: usize,
+        key: String,
+        command: Command,
+    },
+    /// Intervention from outside of the game controller, applied before the step of the frame `t`
+    Intervention {
+        t: usize,
+        intervention: Intervention,
+    },
+    /// Control of a robot driven by hand at the frame `t`, written when it changes or kicks. None once the robot is released
+    Drive {
+        t: usize,
+        robot: Robot,
+        control: Option<(f32, f32, f32)>,
+        kick: Option<f32>,
+    },
+    Event {
+        t: usize,
+        event: RefereeEvent,
+    },
+    /// Debug layer drawn at the frame `t` (see `debug`). The layers drawn before the recording are written after the first game state
+    Debug {
+        t: usize,
+        layer: Layer,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Bitcode,
+    JsonLines,
+}
+impl Format {
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl") => Format::JsonLines,
+            _ => Format::Bitcode
+        }
+    }
+}
+
+fn invalid_data(e: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    format: Format,
+    /// Set after a write error, the next entries are ignored
+    failed: bool,
+}
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            format: Format::from_path(path),
+            failed: false
+        })
+    }
+    /// Writes an entry. The game states are flushed so that the file stays readable if the program is stopped
+    pub fn write(&mut self, entry: &RecordEntry) {
+        if self.failed {
+            return
+        }
+        if let Err(e) = self.try_write(entry) {
+            warn!("Error when writing the recording, it is stopped : {}", e);
+            self.failed = true;
+        }
+    }
+    fn try_write(&mut self, entry: &RecordEntry) -> io::Result<()> {
+        match self.format {
+            Format::JsonLines => {
+                serde_json::to_writer(&mut self.writer, entry)?;
+                self.writer.write_all(b"\n")?;
+            },
+            Format::Bitcode => {
+                let bits = bitcode::serialize(entry).map_err(invalid_data)?;
+                self.writer.write_all(&(bits.len() as u32).to_le_bytes())?;
+                self.writer.write_all(&bits)?;
+            }
+        }
+        if let RecordEntry::Frame { .. } = entry {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads all the entries of a recording
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<RecordEntry>> {
+    let path = path.as_ref();
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    let mut entries = Vec::new();
+    match Format::from_path(path) {
+        Format::JsonLines => for line in data.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            entries.push(serde_json::from_slice(line).map_err(invalid_data)?);
+        },
+        Format::Bitcode => {
+            let mut rest = data.as_slice();
+            while rest.len() >= 4 {
+                let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+                rest = &rest[4..];
+                if rest.len() < len {
+                    // Last entry not completely written
+                    warn!("Truncated recording");
+                    break
+                }
+                entries.push(bitcode::deserialize(&rest[..len]).map_err(invalid_data)?);
+                rest = &rest[len..];
+            }
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point2;
+
+    use super::*;
+    use crate::debug::{Primitive, Shape};
+    use crate::game_state::Team;
+    use crate::native::gc::Entity;
+
+    fn entries() -> Vec<RecordEntry> {
+        let mut state = GameState::default();
+        state.ball = Some(Point2::new(0.25, -0.5));
+        vec![
+            RecordEntry::Config(RecordConfig {
+                blue_team_name: "Blue team".to_string(),
+                green_team_name: "Green team".to_string(),
+                blue_team_positive: true,
+                physics: PhysicsParams::default(),
+                frame_period: 25,
+            }),
+            RecordEntry::Frame { t: 0, state },
+            RecordEntry::Command { t: 3, key: "key".to_string(), command: Command::Control(Robot::Blue2, (0.5, 0., 1.25)) },
+            RecordEntry::Intervention { t: 4, intervention: Intervention::Teleport { entity: Entity::Ball, position: Point2::new(0.5, 0.25), orientation: None } },
+            RecordEntry::Drive { t: 5, robot: Robot::Green1, control: Some((0.25, 0.5, 0.)), kick: Some(1.) },
+            RecordEntry::Event { t: 6, event: RefereeEvent::Goal(Team::Green) },
+            RecordEntry::Debug { t: 7, layer: Layer {
+                team: Team::Blue,
+                namespace: "targets".to_string(),
+                primitives: vec![Primitive { shape: Shape::Point { position: Point2::new(0.5, 0.5) }, color: None }]
+            } },
+        ]
+    }
+
+    /// Unique file in the temporary directory
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rsk_record_{}_{}", std::process::id(), name))
+    }
+
+    fn round_trip(path: &Path) -> Vec<RecordEntry> {
+        let mut recorder = Recorder::create(path).unwrap();
+        for entry in entries() {
+            recorder.write(&entry);
+        }
+        drop(recorder);
+        let read = read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        read
+    }
+
+    fn check(read: &[RecordEntry]) {
+        assert_eq!(read.len(), entries().len());
+        assert!(matches!(&read[0], RecordEntry::Config(c) if c.green_team_name == "Green team" && c.blue_team_positive && c.frame_period == 25));
+        assert!(matches!(&read[1], RecordEntry::Frame { t: 0, state } if state.ball == Some(Point2::new(0.25, -0.5))));
+        assert!(matches!(&read[2], RecordEntry::Command { t: 3, key, command: Command::Control(Robot::Blue2, (x, y, r)) }
+            if key == "key" && (*x, *y, *r) == (0.5, 0., 1.25)));
+        assert!(matches!(&read[3], RecordEntry::Intervention { t: 4, intervention: Intervention::Teleport { entity: Entity::Ball, position, orientation: None } }
+            if *position == Point2::new(0.5, 0.25)));
+        assert!(matches!(&read[4], RecordEntry::Drive { t: 5, robot: Robot::Green1, control: Some((x, y, r)), kick: Some(kick) }
+            if (*x, *y, *r, *kick) == (0.25, 0.5, 0., 1.)));
+        assert!(matches!(&read[5], RecordEntry::Event { t: 6, event: RefereeEvent::Goal(Team::Green) }));
+        match (&read[6], &entries()[6]) {
+            (RecordEntry::Debug { t: 7, layer }, RecordEntry::Debug { layer: expected, .. }) => assert_eq!(layer, expected),
+            (entry, _) => panic!("Unexpected entry {:?}", entry)
+        }
+    }
+
+    #[test]
+    fn json_lines_round_trip() {
+        let path = path("round_trip.jsonl");
+        let read = round_trip(&path);
+        check(&read);
+    }
+
+    #[test]
+    fn bitcode_round_trip() {
+        let path = path("round_trip.rec");
+        let read = round_trip(&path);
+        check(&read);
+    }
+
+    #[test]
+    fn truncated_bitcode_entry_is_skipped() {
+        let path = path("truncated.rec");
+        let mut recorder = Recorder::create(&path).unwrap();
+        for entry in entries() {
+            recorder.write(&entry);
+        }
+        drop(recorder);
+        let len = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 2).unwrap();
+        let read = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), entries().len() - 1);
+    }
+}
@@ -8,6 +8,7 @@ use rapier2d_f64::math::{Rotation, Vector};
 #[cfg(not(target_arch = "wasm32"))]
 use tokio::sync::Mutex;
 use crate::{game_state::GameState, simulation::Simulation, GC};
+use crate::native::gc::Intervention;
 use crate::constants::*;
 use crate::game_state::{Referee as GSReferee, RefereeTeam, RefereeTeamRobot, RefereeTeamRobots, RefereeTeams, Robot, RobotTasks};
 
@@ -18,6 +19,12 @@ pub type TasksType = Arc<Mutex<[RobotTasks; 4]>>;
 #[cfg(target_arch = "wasm32")]
 pub type TasksType = Rc<RefCell<[RobotTasks; 4]>>;
 
+/// Preemption reason of the parked robots during a penalty shootout
+pub(crate) const SHOOTOUT: &str = "Penalty shootout";
+
+/// Reasons of the penalties given by the referee and from the GUI
+pub const PENALTY_REASONS: [&str; 4] = ["Manually penalized", "Ball abuse", "Out of the field", "Intrusion in the defense area"];
+
 #[derive(Debug)]
 struct Team {
     name: String,
@@ -33,23 +40,108 @@ enum PlayState {
     /// Timer before the pause
     GamePaused(usize),
     Halftime,
+    Shootout(Shootout),
 }
 impl From<PlayState> for String {
     fn from(val: PlayState) -> Self {
         match val {
             PlayState::Nothing => "Game is ready to start".to_string(),
+            PlayState::Shootout(Shootout { winner: Some(team), goals, .. }) => format!("{:?} wins the penalty shootout ({} - {})", team, goals[0], goals[1]),
+            PlayState::Shootout(Shootout { kicker, goals, .. }) => format!("Penalty shootout : {:?} kicks ({} - {})", kicker, goals[0], goals[1]),
             _ => "".to_string(), // TODO
         }
     }
 }
 
+/// Penalty shootout : the teams kick in turn from the penalty mark in front of the opponent goal,
+/// with their robot 2 against the robot 1 of the opponent (the goalkeeper of the built-in strategies)
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+struct Shootout {
+    /// Team of the current attempt
+    kicker: crate::game_state::Team,
+    /// Start of the current attempt in frames
+    start: usize,
+    /// Finished attempts of [blue, green]
+    attempts: [usize; 2],
+    /// Goals of [blue, green]
+    goals: [usize; 2],
+    winner: Option<crate::game_state::Team>,
+}
+impl Shootout {
+    /// Winner when the other team can't catch up anymore. After SHOOTOUT_ROUNDS attempts each,
+    /// the teams kick until one of them scored more with the same number of attempts
+    fn decided(&self) -> Option<crate::game_state::Team> {
+        let total = self.attempts[0].max(self.attempts[1]).max(SHOOTOUT_ROUNDS);
+        let [blue, green] = self.goals;
+        if blue > green + total - self.attempts[1] {
+            Some(crate::game_state::Team::Blue)
+        } else if green > blue + total - self.attempts[0] {
+            Some(crate::game_state::Team::Green)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decisions of the referee, kept for the match recordings
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RefereeEvent {
+    /// Team that scored
+    Goal(crate::game_state::Team),
+    BallOut,
+    /// (robot, reason)
+    Penalty(Robot, String),
+    PenaltyEnd(Robot),
+    /// Penalty removed by the referee before its end
+    Unpenalized(Robot),
+    /// Team whose goal was cancelled
+    GoalCancelled(crate::game_state::Team),
+    Start,
+    Pause,
+    Resume,
+    Halftime,
+    /// Start of a penalty shootout, with the team that kicks first
+    Shootout(crate::game_state::Team),
+    /// (kicking team, scored)
+    ShootoutAttempt(crate::game_state::Team, bool),
+    ShootoutWinner(crate::game_state::Team),
+}
+
+/// Rules checked by the referee, all of them by default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Rules {
+    /// Counts the goals and puts the robots and the ball back to their positions
+    pub goals: bool,
+    /// Puts the ball on the closest dot when it leaves the field
+    pub ball_out: bool,
+    /// Penalizes the robots that stay too long close to the ball
+    pub ball_abuse: bool,
+}
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            goals: true,
+            ball_out: true,
+            ball_abuse: true,
+        }
+    }
+}
+
 pub struct Referee {
     /// [blue, green]
     teams: [Team; 2],
     blue_team_positive: bool,
     state: PlayState,
     pub tasks: TasksType,
-    with_ball: [usize; 4]
+    with_ball: [usize; 4],
+    /// Team that scored the last goal, until it is cancelled
+    last_goal: Option<crate::game_state::Team>,
+    /// Events since the last step of the game controller
+    pub events: Vec<RefereeEvent>,
+    pub rules: Rules,
 }
 impl Referee {
     pub fn new(
@@ -75,7 +167,86 @@ impl Referee {
             blue_team_positive,
             state: PlayState::GameRunning(0),
             tasks: TasksType::default(),
-            with_ball: [0; 4]
+            with_ball: [0; 4],
+            last_goal: None,
+            events: Vec::new(),
+            rules: Rules::default(),
+        }
+    }
+    /// Starts a new match at frame `t`
+    pub fn reset(&mut self, t: usize) {
+        for team in self.teams.iter_mut() {
+            team.score = 0;
+        }
+        self.state = PlayState::GameRunning(t);
+        self.with_ball = [t; 4];
+        self.last_goal = None;
+        self.events.clear();
+        for task in self.lock_tasks_mut().iter_mut() {
+            *task = RobotTasks::default();
+        }
+    }
+    /// True when the robots can move : during the match or a penalty shootout
+    pub fn is_running(&self) -> bool {
+        matches!(self.state, PlayState::GameRunning(_) | PlayState::Shootout(Shootout { winner: None, .. }))
+    }
+    /// Starts the match at frame `t` with the clock at 0
+    pub fn start(&mut self, t: usize) {
+        self.state = PlayState::GameRunning(t);
+        self.with_ball = [t; 4];
+        // The robots parked by a penalty shootout play again
+        for task in self.lock_tasks_mut().iter_mut() {
+            if task.driven == Some(SHOOTOUT) {
+                task.driven = None;
+            }
+        }
+        self.events.push(RefereeEvent::Start);
+    }
+    /// Stops the clock
+    pub fn pause(&mut self, t: usize) {
+        if let PlayState::GameRunning(start) = self.state {
+            self.state = PlayState::GamePaused(t - start);
+            self.events.push(RefereeEvent::Pause);
+        }
+    }
+    /// Restarts the clock after a pause or the halftime
+    pub fn resume(&mut self, t: usize) {
+        let elapsed = match self.state {
+            PlayState::GamePaused(timer) => timer,
+            PlayState::Halftime => (MATCH_DURATION.as_millis() as usize / FRAME_DURATION) / 2,
+            PlayState::Nothing => 0,
+            PlayState::GameRunning(_) | PlayState::Shootout(_) => return
+        };
+        // The clock can't be set before the first frame, it restarts lower
+        self.state = PlayState::GameRunning(t.saturating_sub(elapsed));
+        self.with_ball = [t; 4];
+        self.events.push(RefereeEvent::Resume);
+    }
+    pub fn halftime(&mut self) {
+        if self.state != PlayState::Halftime {
+            self.state = PlayState::Halftime;
+            self.events.push(RefereeEvent::Halftime);
+        }
+    }
+    /// Removes the last goal from the score. Returns false if there is no goal to cancel
+    pub fn cancel_goal(&mut self) -> bool {
+        match self.last_goal.take() {
+            Some(team) => {
+                let score = &mut self.teams[team as usize].score;
+                *score = score.saturating_sub(1);
+                self.events.push(RefereeEvent::GoalCancelled(team));
+                true
+            },
+            None => false
+        }
+    }
+    /// Ends the penalty of a robot
+    pub fn unpenalize(&mut self, r: Robot) {
+        let mut tasks = self.lock_tasks_mut();
+        if tasks[r as usize].penalty.take().is_some() {
+            tasks[r as usize].control = (0., 0., 0.);
+            drop(tasks);
+            self.events.push(RefereeEvent::Unpenalized(r));
         }
     }
     #[cfg(not(target_arch = "wasm32"))]
@@ -103,40 +274,8 @@ impl Referee {
                     x_positive: self.blue_team_positive,
                     score: self.teams[0].score,
                     robots: RefereeTeamRobots {
-                        one: if let Some((reason, end, _)) = &tasks[0].penalty {
-                            RefereeTeamRobot {
-                                penalized: true,
-                                penalized_remaining: Some(end.saturating_sub(t) * FRAME_DURATION / 1000),
-                                penalized_reason: Some(reason.to_string()),
-                                preempted: true,
-                                preemption_reasons: vec![reason.to_string()]
-                            }
-                        } else {
-                            RefereeTeamRobot {
-                                penalized: false,
-                                penalized_remaining: None,
-                                penalized_reason: None,
-                                preempted: false,
-                                preemption_reasons: vec![]
-                            }
-                        },
-                        two: if let Some((reason, end, _)) = &tasks[1].penalty {
-                            RefereeTeamRobot {
-                                penalized: true,
-                                penalized_remaining: Some(end.saturating_sub(t) * FRAME_DURATION / 1000),
-                                penalized_reason: Some(reason.to_string()),
-                                preempted: true,
-                                preemption_reasons: vec![reason.to_string()]
-                            }
-                        } else {
-                            RefereeTeamRobot {
-                                penalized: false,
-                                penalized_remaining: None,
-                                penalized_reason: None,
-                                preempted: false,
-                                preemption_reasons: vec![]
-                            }
-                        },
+                        one: gs_robot(&tasks[0], t),
+                        two: gs_robot(&tasks[1], t),
                     },
                 },
                 green: RefereeTeam {
@@ -144,66 +283,59 @@ impl Referee {
                     x_positive: !self.blue_team_positive,
                     score: self.teams[1].score,
                     robots: RefereeTeamRobots {
-                        one: if let Some((reason, end, _)) = &tasks[2].penalty {
-                            RefereeTeamRobot {
-                                penalized: true,
-                                penalized_remaining: Some(end.saturating_sub(t) * FRAME_DURATION / 1000),
-                                penalized_reason: Some(reason.to_string()),
-                                preempted: true,
-                                preemption_reasons: vec![reason.to_string()]
-                            }
-                        } else {
-                            RefereeTeamRobot {
-                                penalized: false,
-                                penalized_remaining: None,
-                                penalized_reason: None,
-                                preempted: false,
-                                preemption_reasons: vec![]
-                            }
-                        },
-                        two: if let Some((reason, end, _)) = &tasks[3].penalty {
-                            RefereeTeamRobot {
-                                penalized: true,
-                                penalized_remaining: Some(end.saturating_sub(t) * FRAME_DURATION / 1000),
-                                penalized_reason: Some(reason.to_string()),
-                                preempted: true,
-                                preemption_reasons: vec![reason.to_string()]
-                            }
-                        } else {
-                            RefereeTeamRobot {
-                                penalized: false,
-                                penalized_remaining: None,
-                                penalized_reason: None,
-                                preempted: false,
-                                preemption_reasons: vec![]
-                            }
-                        },
+                        one: gs_robot(&tasks[2], t),
+                        two: gs_robot(&tasks[3], t),
                     },
                 },
             },
-            game_is_running: if let PlayState::GameRunning(_) = self.state {true} else {false},
+            game_is_running: self.is_running(),
             game_paused: if let PlayState::GamePaused(_) = self.state {true} else {false},
             halftime_is_running: self.state == PlayState::Halftime,
             timer: match self.state {
                 PlayState::GameRunning(start) => (t - start) * FRAME_DURATION / 1000,
                 PlayState::GamePaused(timer) => timer * FRAME_DURATION / 1000,
                 PlayState::Halftime => MATCH_DURATION.as_secs() as usize/2,
-                PlayState::Nothing => 0
+                // Time of the current attempt
+                PlayState::Shootout(Shootout { winner: None, start, .. }) => (t - start) * FRAME_DURATION / 1000,
+                PlayState::Shootout(_) | PlayState::Nothing => 0
             },
             game_state_msg: self.state.into(),
         }
     }
 }
+/// Penalty and preemption of a robot in the game state
+fn gs_robot(task: &RobotTasks, t: usize) -> RefereeTeamRobot {
+    match &task.penalty {
+        Some((reason, end, _)) => RefereeTeamRobot {
+            penalized: true,
+            penalized_remaining: Some(end.saturating_sub(t) * FRAME_DURATION / 1000),
+            penalized_reason: Some(reason.to_string()),
+            preempted: true,
+            preemption_reasons: vec![reason.to_string()]
+        },
+        None => RefereeTeamRobot {
+            penalized: false,
+            penalized_remaining: None,
+            penalized_reason: None,
+            preempted: task.driven.is_some(),
+            preemption_reasons: task.preemption().into_iter().map(String::from).collect()
+        }
+    }
+}
+
 impl GC {
     pub fn referee_step(&mut self) {
         use rapier2d_f64::math::Point;
         use tracing::info;
 
+        if let PlayState::Shootout(shootout) = self.referee.state {
+            self.shootout_step(shootout);
+        }
         if let PlayState::GameRunning(_) = self.referee.state {
             let gs = self.get_game_state();
             let mut ball = gs.ball.unwrap();
             // Check for goals
-            if ball.y.abs() < real::GOAL_HEIGHT/2. {
+            if self.referee.rules.goals && ball.y.abs() < real::GOAL_HEIGHT/2. {
                 if ball.x < -real::FIELD.0/2. {
                     self.referee.teams[1].score += 1;
                     for t in self.referee.lock_tasks_mut().iter_mut() {
@@ -211,6 +343,8 @@ impl GC {
                     }
                     self.reset();
                     ball = real::DEFAULT_BALL_POS;
+                    self.referee.last_goal = Some(crate::game_state::Team::Green);
+                    self.referee.events.push(RefereeEvent::Goal(crate::game_state::Team::Green));
                     info!(target:"referee", "Green scored!");
                 } else if ball.x > real::FIELD.0/2. {
                     self.referee.teams[0].score += 1;
@@ -219,12 +353,15 @@ impl GC {
                     }
                     self.reset();
                     ball = real::DEFAULT_BALL_POS;
+                    self.referee.last_goal = Some(crate::game_state::Team::Blue);
+                    self.referee.events.push(RefereeEvent::Goal(crate::game_state::Team::Blue));
                     info!(target:"referee", "Blue scored!");
                 }
             }
             // Check out of field
-            if ball.y.abs() > real::FIELD.1/2. || ball.x.abs() > real::FIELD.0/2. {
+            if self.referee.rules.ball_out && (ball.y.abs() > real::FIELD.1/2. || ball.x.abs() > real::FIELD.0/2.) {
                 self.teleport_entity(self.simu.ball, Point::new(real::DOT_POS.0*ball.x.signum(), real::DOT_POS.1*ball.y.signum()), None);
+                self.referee.events.push(RefereeEvent::BallOut);
                 info!(target:"referee", "Ball out of field");
             }
             // Check with ball
@@ -233,8 +370,8 @@ impl GC {
                 || self.referee.lock_tasks()[r as usize].penalty.is_some() {
                     self.referee.with_ball[r as usize] = self.simu.t;
                 }
-                if self.simu.t - self.referee.with_ball[r as usize] > BALL_ABUSE_TIME {
-                    self.penalize(r, "Ball abuse");
+                if self.referee.rules.ball_abuse && self.simu.t - self.referee.with_ball[r as usize] > BALL_ABUSE_TIME {
+                    self.penalize(r, PENALTY_REASONS[1]);
                 }
             }
             // for ((with_ball, handle), r) in self.referee.with_ball.iter_mut().zip(self.simu.robots).zip(Robot::all()) {
@@ -245,11 +382,13 @@ impl GC {
             //         self.penalize(r, "Ball abuse");
             //     }
             // }
+            let mut ended = Vec::new();
             for (t, r) in self.referee.lock_tasks_mut().iter_mut().zip(Robot::all()) {
                 if let Some((_, end, spot)) = t.penalty {
                     if end < self.simu.t {
                         t.penalty = None;
                         t.control = (0., 0., 0.);
+                        ended.push(r);
                     } else {
                         // goto DIY
                         // TODO: maybe make it a copy of the official goto
@@ -276,15 +415,115 @@ impl GC {
                     }
                 }
             }
+            self.referee.events.extend(ended.into_iter().map(RefereeEvent::PenaltyEnd));
+        }
+    }
+    /// Starts a penalty shootout, `first` kicks the first attempt. The scores of the match are kept
+    pub fn start_shootout(&mut self, first: crate::game_state::Team) {
+        self.recorded(Some(Intervention::Shootout(first)), |gc| {
+            gc.referee.state = PlayState::Shootout(Shootout {
+                kicker: first,
+                start: gc.simu.t,
+                attempts: [0; 2],
+                goals: [0; 2],
+                winner: None,
+            });
+            gc.referee.events.push(RefereeEvent::Shootout(first));
+            tracing::info!(target:"referee", "Penalty shootout, {:?} kicks first", first);
+            gc.setup_shootout_attempt(first);
+        })
+    }
+    /// Puts the ball on the penalty mark, the kicker behind it and the goalkeeper on its goal line.
+    /// The other robots are parked outside of the field
+    fn setup_shootout_attempt(&mut self, kicker: crate::game_state::Team) {
+        let defender = kicker.opponent();
+        let (attacker, goalkeeper) = (kicker.robot(2), defender.robot(1));
+        let goal = defender.goal(&self.referee.get_gs_referee(self.simu.t));
+        let mark = real::PENALTY_MARKS[(goal.x > 0.) as usize];
+        let direction = (goal - mark).normalize();
+        let ball = self.get_ball_handle();
+        self.teleport_entity(ball, mark, None);
+        self.set_velocity(ball, Vector::zeros());
+        for r in Robot::all() {
+            let (position, orientation) = if r == attacker {
+                (mark - direction*2.*real::ROBOT_RADIUS, direction.y.atan2(direction.x))
+            } else if r == goalkeeper {
+                (goal - direction*real::ROBOT_RADIUS, (-direction.y).atan2(-direction.x))
+            } else {
+                (real::PARKING_SPOTS[r as usize], 0.)
+            };
+            let handle = self.get_robot_handle(r);
+            self.teleport_entity(handle, position, Some(orientation));
+            self.set_velocity(handle, Vector::zeros());
+        }
+        let mut tasks = self.referee.lock_tasks_mut();
+        for (task, r) in tasks.iter_mut().zip(Robot::all()) {
+            task.penalty = None;
+            task.control = (0., 0., 0.);
+            task.kick = None;
+            if r != attacker && r != goalkeeper {
+                task.driven = Some(SHOOTOUT);
+            } else if task.driven == Some(SHOOTOUT) {
+                task.driven = None;
+            }
+        }
+        drop(tasks);
+        self.referee.with_ball = [self.simu.t; 4];
+    }
+    /// Ends the attempt when the ball enters the goal, leaves the field or after SHOOTOUT_ATTEMPT_TIME
+    fn shootout_step(&mut self, mut shootout: Shootout) {
+        use tracing::info;
+
+        if shootout.winner.is_some() {
+            return
+        }
+        let kicker = shootout.kicker;
+        let defender = kicker.opponent();
+        // The parked robots can be pushed
+        for r in Robot::all() {
+            if r != kicker.robot(2) && r != defender.robot(1) {
+                let handle = self.get_robot_handle(r);
+                self.teleport_entity(handle, real::PARKING_SPOTS[r as usize], Some(0.));
+            }
+        }
+        let ball = self.simu.bodies[self.simu.ball].translation() / simu::MULTIPLIER;
+        let goal = defender.goal(&self.referee.get_gs_referee(self.simu.t));
+        let scored = ball.y.abs() < real::GOAL_HEIGHT/2. && ball.x * goal.x.signum() > real::FIELD.0/2.;
+        let missed = ball.x.abs() > real::FIELD.0/2. || ball.y.abs() > real::FIELD.1/2.
+            || self.simu.t - shootout.start > SHOOTOUT_ATTEMPT_TIME;
+        if !scored && !missed {
+            return
+        }
+        shootout.attempts[kicker as usize] += 1;
+        if scored {
+            shootout.goals[kicker as usize] += 1;
         }
+        self.referee.events.push(RefereeEvent::ShootoutAttempt(kicker, scored));
+        info!(target:"referee", "{:?} {} its penalty ({} - {})", kicker, if scored {"scored"} else {"missed"}, shootout.goals[0], shootout.goals[1]);
+        shootout.winner = shootout.decided();
+        if let Some(winner) = shootout.winner {
+            self.referee.events.push(RefereeEvent::ShootoutWinner(winner));
+            info!(target:"referee", "{:?} wins the penalty shootout", winner);
+        } else {
+            shootout.kicker = defender;
+            shootout.start = self.simu.t;
+            self.setup_shootout_attempt(defender);
+        }
+        self.referee.state = PlayState::Shootout(shootout);
+    }
+    pub fn penalize(&mut self, r: Robot, reason: &'static str) {
+        // The penalties of the referee itself are not recorded
+        self.recorded(Some(Intervention::Penalize(r, reason.to_string())), |gc| gc.penalize_robot(r, reason))
     }
-    pub fn penalize(&self, r: Robot, reason: &'static str) {
+    fn penalize_robot(&mut self, r: Robot, reason: &'static str) {
         dbg!("penalize");
         let mut tasks = self.referee.lock_tasks_mut();
 
         if let Some(p) = tasks[r as usize].penalty.as_mut() {
             p.0 = reason;
             p.1 += PENALTY_DURATION;
+            drop(tasks);
+            self.referee.events.push(RefereeEvent::Penalty(r, reason.to_string()));
             return
         }
 
@@ -315,5 +554,47 @@ impl GC {
             .unwrap_or(0);
         
         tasks[r as usize].penalty = Some((reason, self.simu.t+PENALTY_DURATION, spot));
+        drop(tasks);
+        self.referee.events.push(RefereeEvent::Penalty(r, reason.to_string()));
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::Team as GSTeam;
+
+    fn shootout(attempts: [usize; 2], goals: [usize; 2]) -> Shootout {
+        Shootout {
+            kicker: GSTeam::Blue,
+            start: 0,
+            attempts,
+            goals,
+            winner: None,
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn shootout_is_decided_when_the_other_team_cant_catch_up() {
+        assert_eq!(shootout([3, 3], [3, 0]).decided(), Some(GSTeam::Blue));
+        // Green can still score its 3 remaining attempts
+        assert_eq!(shootout([3, 2], [3, 0]).decided(), None);
+        assert_eq!(shootout([4, 4], [1, 3]).decided(), Some(GSTeam::Green));
+    }
+
+    #[test]
+    fn shootout_is_decided_after_the_rounds() {
+        assert_eq!(shootout([SHOOTOUT_ROUNDS; 2], [4, 3]).decided(), Some(GSTeam::Blue));
+        assert_eq!(shootout([SHOOTOUT_ROUNDS; 2], [2, 3]).decided(), Some(GSTeam::Green));
+        assert_eq!(shootout([SHOOTOUT_ROUNDS; 2], [3, 3]).decided(), None);
+    }
+
+    #[test]
+    fn sudden_death_needs_the_same_number_of_attempts() {
+        let rounds = SHOOTOUT_ROUNDS;
+        // Blue scored its attempt of the sudden death, green still kicks
+        assert_eq!(shootout([rounds + 1, rounds], [4, 3]).decided(), None);
+        assert_eq!(shootout([rounds + 1, rounds + 1], [4, 3]).decided(), Some(GSTeam::Blue));
+        assert_eq!(shootout([rounds + 1, rounds + 1], [4, 4]).decided(), None);
+        assert_eq!(shootout([rounds + 2, rounds + 2], [4, 5]).decided(), Some(GSTeam::Green));
+    }
+}
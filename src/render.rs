@@ -0,0 +1,323 @@
+//! Software rendering of game states to SVG, without Bevy nor GPU (for reports and CI).
+//! The field has the same geometry as the GUI, which takes it from `field_rectangles`.
+
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+use nalgebra::Point2;
+
+use crate::constants::real::*;
+use crate::debug::{DebugDrawings, Shape};
+use crate::game_state::{GameState, Robot, Team};
+
+pub const GRASS_COLOR: [f32; 3] = [68./255., 170./255., 1./255.];
+pub const BLUE_COLOR: [f32; 3] = [0., 0., 1.];
+pub const GREEN_COLOR: [f32; 3] = [0., 1., 0.];
+pub const BALL_COLOR: [f32; 3] = [247./255., 107./255., 49./255.];
+pub const PENALIZED_COLOR: [f32; 3] = [0.3, 0.3, 0.3];
+pub const KICKER_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+pub const BLACK: [f32; 3] = [0., 0., 0.];
+pub const WHITE: [f32; 3] = [1., 1., 1.];
+/// Width of the lines of the field, in meters
+pub const LINE_WIDTH: f64 = 0.02;
+/// Pixels per meter of the rendered images
+pub const SCALE: f64 = 400.;
+
+/// Rectangle centered on (x, y), in meters
+pub struct FieldRectangle {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub color: [f32; 3],
+}
+
+/// Lines of the defense areas, borders of the field and goal posts
+pub fn field_rectangles() -> Vec<FieldRectangle> {
+    let rectangle = |x: f64, y: f64, width: f64, height: f64, color: [f32; 3]| FieldRectangle { x, y, width, height, color };
+    vec![
+        // Defense areas
+        rectangle((FIELD.0-DEFENSE_AREA.0)/2., DEFENSE_AREA.1/2., DEFENSE_AREA.0 + LINE_WIDTH, LINE_WIDTH, BLACK),
+        rectangle((FIELD.0-DEFENSE_AREA.0)/2., -DEFENSE_AREA.1/2., DEFENSE_AREA.0 + LINE_WIDTH, LINE_WIDTH, BLACK),
+        rectangle((FIELD.0/2.)-DEFENSE_AREA.0, 0., LINE_WIDTH, DEFENSE_AREA.1 + LINE_WIDTH, BLACK),
+        rectangle(-(FIELD.0-DEFENSE_AREA.0)/2., DEFENSE_AREA.1/2., DEFENSE_AREA.0 + LINE_WIDTH, LINE_WIDTH, BLACK),
+        rectangle(-(FIELD.0-DEFENSE_AREA.0)/2., -DEFENSE_AREA.1/2., DEFENSE_AREA.0 + LINE_WIDTH, LINE_WIDTH, BLACK),
+        rectangle(DEFENSE_AREA.0-(FIELD.0/2.), 0., LINE_WIDTH, DEFENSE_AREA.1 + LINE_WIDTH, BLACK),
+
+        // Borders of the field
+        rectangle(0., FIELD.1/2., FIELD.0 + LINE_WIDTH, LINE_WIDTH, WHITE),
+        rectangle(0., -FIELD.1/2., FIELD.0 + LINE_WIDTH, LINE_WIDTH, WHITE),
+        rectangle(FIELD.0/2., 0., LINE_WIDTH, FIELD.1 + LINE_WIDTH, WHITE),
+        rectangle(-FIELD.0/2., 0., LINE_WIDTH, FIELD.1 + LINE_WIDTH, WHITE),
+
+        // Goals
+        rectangle(GREEN_GOAL.0.x, GREEN_GOAL.0.y, LINE_WIDTH, 0.01, BLACK),
+        rectangle(GREEN_GOAL.1.x, GREEN_GOAL.1.y, LINE_WIDTH, 0.01, BLACK),
+        rectangle(BLUE_GOAL.0.x, BLUE_GOAL.0.y, LINE_WIDTH, 0.01, BLACK),
+        rectangle(BLUE_GOAL.1.x, BLUE_GOAL.1.y, LINE_WIDTH, 0.01, BLACK),
+    ]
+}
+
+pub fn team_color(team: Team) -> [f32; 3] {
+    match team {
+        Team::Blue => BLUE_COLOR,
+        Team::Green => GREEN_COLOR
+    }
+}
+
+fn rgb(color: [f32; 3]) -> String {
+    let [r, g, b] = color.map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+    format!("rgb({},{},{})", r, g, b)
+}
+
+/// Escapes the text for xml
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The y axis of SVG points down
+fn svg(p: Point2<f64>) -> (f64, f64) {
+    (p.x, -p.y)
+}
+
+fn polygon(out: &mut String, points: impl IntoIterator<Item = Point2<f64>>, color: [f32; 3]) {
+    let points: Vec<String> = points.into_iter().map(|p| {
+        let (x, y) = svg(p);
+        format!("{:.4},{:.4}", x, y)
+    }).collect();
+    let _ = writeln!(out, r#"<polygon points="{}" fill="{}"/>"#, points.join(" "), rgb(color));
+}
+
+fn text(out: &mut String, p: Point2<f64>, size: f64, anchor: &str, color: [f32; 3], content: &str) {
+    let (x, y) = svg(p);
+    // One tspan per line
+    let _ = write!(out, r#"<text x="{:.4}" y="{:.4}" font-size="{}" font-family="sans-serif" text-anchor="{}" fill="{}">"#, x, y, size, anchor, rgb(color));
+    for (i, line) in content.lines().enumerate() {
+        let _ = write!(out, r#"<tspan x="{:.4}" dy="{}">{}</tspan>"#, x, if i == 0 {0.} else {size * 1.2}, escape(line));
+    }
+    let _ = writeln!(out, "</text>");
+}
+
+fn line(out: &mut String, from: Point2<f64>, to: Point2<f64>, color: [f32; 3]) {
+    let ((x1, y1), (x2, y2)) = (svg(from), svg(to));
+    let _ = writeln!(out, r#"<line x1="{:.4}" y1="{:.4}" x2="{:.4}" y2="{:.4}" stroke="{}" stroke-width="0.005"/>"#, x1, y1, x2, y2, rgb(color));
+}
+
+fn circle(out: &mut String, center: Point2<f64>, radius: f64, fill: Option<[f32; 3]>, stroke: Option<[f32; 3]>) {
+    let (x, y) = svg(center);
+    let fill = fill.map(rgb).unwrap_or_else(|| "none".to_string());
+    let stroke = stroke.map(|c| format!(r#" stroke="{}" stroke-width="0.005""#, rgb(c))).unwrap_or_default();
+    let _ = writeln!(out, r#"<circle cx="{:.4}" cy="{:.4}" r="{:.4}" fill="{}"{}/>"#, x, y, radius, fill, stroke);
+}
+
+/// Field, robots, ball, HUD and debug drawings, without the svg element
+fn frame_content(gs: &GameState, debug: &DebugDrawings) -> String {
+    let mut out = String::new();
+    // Field
+    polygon(&mut out, [
+        Point2::new(-CARPET.0/2., -CARPET.1/2.), Point2::new(CARPET.0/2., -CARPET.1/2.),
+        Point2::new(CARPET.0/2., CARPET.1/2.), Point2::new(-CARPET.0/2., CARPET.1/2.)
+    ], GRASS_COLOR);
+    circle(&mut out, Point2::origin(), CENTER_CIRCLE_RADIUS, Some(BLACK), None);
+    circle(&mut out, Point2::origin(), CENTER_CIRCLE_RADIUS - LINE_WIDTH, Some(GRASS_COLOR), None);
+    for r in field_rectangles() {
+        polygon(&mut out, [
+            Point2::new(r.x - r.width/2., r.y - r.height/2.), Point2::new(r.x + r.width/2., r.y - r.height/2.),
+            Point2::new(r.x + r.width/2., r.y + r.height/2.), Point2::new(r.x - r.width/2., r.y + r.height/2.)
+        ], r.color);
+    }
+
+    // Robots : hexagons with the kicker on a flat side, in front of the robot
+    let referee = &gs.referee;
+    for r in Robot::all() {
+        let pose = gs.markers.get(r);
+        let o = pose.orientation;
+        let local = |x: f64, y: f64| pose.position + nalgebra::Vector2::new(x*o.cos() - y*o.sin(), x*o.sin() + y*o.cos());
+        let color = if referee.teams.robot(r).penalized {PENALIZED_COLOR} else {team_color(r.team())};
+        polygon(&mut out, (0..6).map(|k| {
+            let a = PI/2. + k as f64 * PI/3.;
+            local(ROBOT_RADIUS * a.cos(), ROBOT_RADIUS * a.sin())
+        }), color);
+        let x = ROBOT_RADIUS * 0.866;
+        polygon(&mut out, [
+            local(x - 0.005, -ROBOT_RADIUS/2.), local(x + 0.005, -ROBOT_RADIUS/2.),
+            local(x + 0.005, ROBOT_RADIUS/2.), local(x - 0.005, ROBOT_RADIUS/2.)
+        ], KICKER_COLOR);
+    }
+    if let Some(ball) = gs.ball {
+        circle(&mut out, ball, BALL_RADIUS, Some(BALL_COLOR), None);
+    }
+
+    // Debug drawings
+    for layer in debug.layers.iter() {
+        for primitive in layer.primitives.iter() {
+            let color = primitive.color.unwrap_or(team_color(layer.team));
+            match &primitive.shape {
+                Shape::Point { position } => circle(&mut out, *position, 0.01, Some(color), None),
+                Shape::Line { from, to } => line(&mut out, *from, *to, color),
+                Shape::Circle { center, radius } => circle(&mut out, *center, *radius, None, Some(color)),
+                Shape::Arrow { from, to } => {
+                    line(&mut out, *from, *to, color);
+                    let d = to - from;
+                    if d.norm() > 0. {
+                        let back = -d.normalize() * 0.03;
+                        for a in [PI/6., -PI/6.] {
+                            let head = nalgebra::Vector2::new(back.x*a.cos() - back.y*a.sin(), back.x*a.sin() + back.y*a.cos());
+                            line(&mut out, *to, to + head, color);
+                        }
+                    }
+                },
+                Shape::Text { position, text: content } => text(&mut out, *position, 0.03, "start", color, content)
+            }
+        }
+    }
+
+    // HUD : like the GUI, the team on the left defends the negative x
+    for left in [true, false] {
+        let team = if referee.teams.blue.x_positive != left {Team::Blue} else {Team::Green};
+        let referee_team = referee.teams.get(team);
+        let name = match (referee_team.name.as_str(), team) {
+            ("", Team::Blue) => "Blue",
+            ("", Team::Green) => "Green",
+            (name, _) => name
+        };
+        let y = CARPET.1/2. - 0.07;
+        if left {
+            text(&mut out, Point2::new(-CARPET.0/2. + 0.03, y), 0.06, "start", team_color(team), &format!("{}  {}", name, referee_team.score));
+        } else {
+            text(&mut out, Point2::new(CARPET.0/2. - 0.03, y), 0.06, "end", team_color(team), &format!("{}  {}", referee_team.score, name));
+        }
+    }
+    let state = if !referee.game_state_msg.is_empty() {
+        referee.game_state_msg.as_str()
+    } else if referee.game_is_running {
+        "Game running"
+    } else if referee.game_paused {
+        "Game paused"
+    } else if referee.halftime_is_running {
+        "Halftime"
+    } else {
+        ""
+    };
+    text(&mut out, Point2::new(0., CARPET.1/2. - 0.07), 0.06, "middle", WHITE, &format!("{:02}:{:02}\n{}", referee.timer / 60, referee.timer % 60, state));
+    for r in Robot::all() {
+        let robot = referee.teams.robot(r);
+        if robot.penalized {
+            let position = gs.markers.get(r).position;
+            text(&mut out, Point2::new(position.x, position.y + ROBOT_RADIUS*2.), 0.035, "middle", WHITE,
+                &format!("{} s\n{}", robot.penalized_remaining.unwrap_or(0), robot.penalized_reason.as_deref().unwrap_or("")));
+        }
+    }
+    out
+}
+
+fn svg_header(out: &mut String) {
+    let _ = writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        (CARPET.0 * SCALE).round(), (CARPET.1 * SCALE).round(), -CARPET.0/2., -CARPET.1/2., CARPET.0, CARPET.1);
+}
+
+/// SVG image of a game state with debug drawings
+pub fn render_svg(gs: &GameState, debug: &DebugDrawings) -> String {
+    let mut out = String::new();
+    svg_header(&mut out);
+    out.push_str(&frame_content(gs, debug));
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Animated SVG (SMIL) showing each (duration in seconds, game state, debug drawings) in turn. The last frame stays visible
+pub fn render_animated_svg(frames: &[(f64, GameState, DebugDrawings)]) -> String {
+    let mut out = String::new();
+    svg_header(&mut out);
+    let mut begin = 0.;
+    for (i, (duration, gs, debug)) in frames.iter().enumerate() {
+        let _ = writeln!(out, r#"<g visibility="hidden">"#);
+        let fill = if i == frames.len() - 1 {"freeze"} else {"remove"};
+        let _ = writeln!(out, r#"<set attributeName="visibility" to="visible" begin="{:.3}s" dur="{:.3}s" fill="{}"/>"#, begin, duration, fill);
+        out.push_str(&frame_content(gs, debug));
+        out.push_str("</g>\n");
+        begin += duration;
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Game states of a recording with their time (in frames) and the debug drawings at that time, at most `fps` per second of match
+#[cfg(feature = "record")]
+pub fn recording_frames(entries: &[crate::record::RecordEntry], fps: f64) -> Vec<(usize, GameState, DebugDrawings)> {
+    use crate::record::RecordEntry;
+
+    let period = ((1. / crate::constants::DT) / fps).max(1.);
+    let mut debug = DebugDrawings::default();
+    let mut frames: Vec<(usize, GameState, DebugDrawings)> = Vec::new();
+    for entry in entries {
+        match entry {
+            RecordEntry::Debug { layer, .. } => debug.set(layer.clone()),
+            RecordEntry::Frame { t, state } => {
+                if frames.last().map_or(true, |(last, _, _)| (*t - *last) as f64 >= period) {
+                    frames.push((*t, state.clone(), debug.clone()));
+                }
+            },
+            _ => ()
+        }
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that the tags are balanced, the text must not contain unescaped '<'
+    fn assert_well_formed(svg: &str) {
+        let mut open = Vec::new();
+        for tag in svg.split('<').skip(1) {
+            let tag = &tag[..tag.find('>').expect("Unclosed tag")];
+            let name = tag.trim_start_matches('/').split(' ').next().unwrap();
+            if tag.starts_with('/') {
+                assert_eq!(open.pop(), Some(name), "Unexpected closing tag {}", name);
+            } else if !tag.ends_with('/') {
+                open.push(name);
+            }
+        }
+        assert!(open.is_empty(), "Unclosed elements {:?}", open);
+    }
+
+    fn state() -> GameState {
+        let mut gs = GameState::default();
+        gs.ball = Some(Point2::new(0.1, 0.2));
+        gs.markers.blue1.position = Point2::new(-0.3, 0.25);
+        gs.referee.teams.blue.name = "<Blue> & co".to_string();
+        gs.referee.teams.blue.x_positive = false;
+        gs
+    }
+
+    #[test]
+    fn svg_contains_the_robots_and_the_ball() {
+        let svg = render_svg(&state(), &DebugDrawings::default());
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_well_formed(&svg);
+        // Carpet, field lines, and the body and kicker of each robot
+        assert_eq!(svg.matches("<polygon").count(), 1 + field_rectangles().len() + 2*4);
+        assert_eq!(svg.matches(&format!(r#"fill="{}"/>"#, rgb(BLUE_COLOR))).count(), 2);
+        assert_eq!(svg.matches(&format!(r#"fill="{}"/>"#, rgb(GREEN_COLOR))).count(), 2);
+        assert_eq!(svg.matches(&format!(r#"fill="{}"/>"#, rgb(BALL_COLOR))).count(), 1);
+    }
+
+    #[test]
+    fn svg_y_axis_is_flipped() {
+        let svg = render_svg(&state(), &DebugDrawings::default());
+        assert!(svg.contains(&format!(r#"<circle cx="0.1000" cy="-0.2000" r="{:.4}" fill="{}"/>"#, BALL_RADIUS, rgb(BALL_COLOR))));
+        // Top vertex of the hexagon of blue1
+        assert!(svg.contains(&format!("-0.3000,{:.4}", -(0.25 + ROBOT_RADIUS))));
+    }
+
+    #[test]
+    fn svg_text_is_escaped() {
+        let svg = render_svg(&state(), &DebugDrawings::default());
+        assert!(svg.contains("&lt;Blue&gt; &amp; co  0"));
+        assert!(!svg.contains("<Blue>"));
+        assert_well_formed(&svg);
+    }
+}
@@ -0,0 +1,297 @@
+//! Reinforcement learning environment around a headless game controller, with an api similar to gymnasium :
+//! ```ignore
+//! let mut env = Env::new(EnvConfig::default());
+//! let mut obs = env.reset(Some(42));
+//! loop {
+//!     let (next_obs, reward, terminated, truncated, info) = env.step(&policy(&obs))?;
+//!     obs = if terminated || truncated { env.reset(None) } else { next_obs };
+//! }
+//! ```
+
+mod randomization;
+pub mod reward;
+mod vec_env;
+
+pub use randomization::Randomization;
+pub use vec_env::VecEnv;
+
+use std::f64::consts::PI;
+use std::fmt;
+
+use nalgebra::Point2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tracing::{info, warn};
+
+use crate::constants::real::*;
+use crate::game_state::{GameState, Robot, Team};
+use crate::simulation::PhysicsParams;
+use crate::strategy::Strategy;
+use crate::GC;
+use reward::{BallProgress, Goals, Reward};
+
+/// Size of the action of one robot : (x, y, rotation, kick)
+pub const ROBOT_ACTION_SIZE: usize = 4;
+/// Random positions tried by `free_position` before using its fallback
+const MAX_PLACEMENT_ATTEMPTS: usize = 1000;
+/// Size of the action of the agent (both robots of its team)
+pub const ACTION_SIZE: usize = 2 * ROBOT_ACTION_SIZE;
+
+/// Parts of the observation vector, in the order of the config. Robots are always ordered with the agent's team first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObsFeature {
+    /// x, y
+    Ball,
+    /// vx, vy
+    BallVelocity,
+    /// x, y, cos(orientation), sin(orientation) of each robot
+    Robots,
+    /// vx, vy, angular velocity of each robot
+    RobotsVelocities,
+    /// 1. if penalized, 0. otherwise, for each robot
+    Penalties,
+}
+impl ObsFeature {
+    pub const fn all() -> [Self; 5] {
+        [Self::Ball, Self::BallVelocity, Self::Robots, Self::RobotsVelocities, Self::Penalties]
+    }
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Ball | Self::BallVelocity => 2,
+            Self::Robots => 4*4,
+            Self::RobotsVelocities => 3*4,
+            Self::Penalties => 4
+        }
+    }
+}
+
+pub struct EnvConfig {
+    /// Team controlled by the agent
+    pub team: Team,
+    pub observation: Vec<ObsFeature>,
+    /// The reward is the sum of all of them
+    pub rewards: Vec<Box<dyn Reward>>,
+    /// Strategy of the other team. If None, its robots don't move
+    pub opponent: Option<Box<dyn Strategy>>,
+    /// Number of frames between two actions
+    pub frame_skip: usize,
+    /// The episode is truncated after this number of steps
+    pub max_steps: usize,
+    /// The episode is terminated when a goal is scored
+    pub terminate_on_goal: bool,
+    /// Random positions of the ball and the robots at each reset
+    pub random_start: bool,
+    /// Physical parameters sampled at each reset. If None, the default ones are used
+    pub randomization: Option<Randomization>,
+}
+impl Default for EnvConfig {
+    fn default() -> Self {
+        Self {
+            team: Team::Blue,
+            observation: ObsFeature::all().to_vec(),
+            rewards: vec![Box::new(Goals::default()), Box::new(BallProgress::default())],
+            opponent: None,
+            frame_skip: (1. / (DT * DEFAULT_DECISION_RATE as f64)) as usize,
+            max_steps: 60 * DEFAULT_DECISION_RATE,
+            terminate_on_goal: true,
+            random_start: true,
+            randomization: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Info {
+    /// Frame of the simulation
+    pub t: usize,
+    /// Team that scored during the step
+    pub goal: Option<Team>,
+}
+
+/// The actions given to `Env::step` or `VecEnv::step` don't have the expected size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ActionSizeError {
+    pub expected: usize,
+    pub actual: usize,
+}
+impl fmt::Display for ActionSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {} actions, got {}", self.expected, self.actual)
+    }
+}
+impl std::error::Error for ActionSizeError {}
+
+pub struct Env {
+    pub gc: GC,
+    config: EnvConfig,
+    rng: StdRng,
+    /// Game state after the last step
+    gs: GameState,
+    steps: usize,
+}
+impl Env {
+    pub fn new(mut config: EnvConfig) -> Self {
+        let mut gc = GC::headless("".to_string(), "".to_string(), "".to_string(), "".to_string(), false);
+        gc.set_extended_state(true);
+        gc.set_strategy(config.team.opponent(), config.opponent.take());
+        let gs = gc.get_game_state();
+        Self {
+            gc,
+            config,
+            rng: StdRng::from_entropy(),
+            gs,
+            steps: 0
+        }
+    }
+    pub fn observation_size(&self) -> usize {
+        self.config.observation.iter().map(|f| f.size()).sum()
+    }
+    pub fn reset(&mut self, seed: Option<u64>) -> Vec<f32> {
+        let mut obs = vec![0.; self.observation_size()];
+        self.reset_into(seed, &mut obs);
+        obs
+    }
+    /// Same as `reset` but writes the observation in `obs`
+    pub fn reset_into(&mut self, seed: Option<u64>, obs: &mut [f32]) {
+        if let Some(seed) = seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+        self.gc.reset_match();
+        if let Some(randomization) = &self.config.randomization {
+            let params = randomization.sample(&mut self.rng);
+            info!("Physics parameters of the episode : {:?}", params);
+            self.gc.set_physics_params(params);
+        }
+        if self.config.random_start {
+            self.randomize_positions();
+        }
+        for reward in self.config.rewards.iter_mut() {
+            reward.reset();
+        }
+        self.steps = 0;
+        self.gs = self.gc.get_game_state();
+        self.observe(obs);
+    }
+    /// `actions` contains (x, y, rotation, kick) for each robot of the agent's team. The robot kicks if kick > 0.
+    /// Returns (observation, reward, terminated, truncated, info), or an error if there are not ACTION_SIZE actions
+    pub fn step(&mut self, actions: &[f32]) -> Result<(Vec<f32>, f64, bool, bool, Info), ActionSizeError> {
+        let mut obs = vec![0.; self.observation_size()];
+        let (reward, terminated, truncated, info) = self.step_into(actions, &mut obs)?;
+        Ok((obs, reward, terminated, truncated, info))
+    }
+    /// Same as `step` but writes the observation in `obs`
+    pub fn step_into(&mut self, actions: &[f32], obs: &mut [f32]) -> Result<(f64, bool, bool, Info), ActionSizeError> {
+        if actions.len() != ACTION_SIZE {
+            return Err(ActionSizeError { expected: ACTION_SIZE, actual: actions.len() })
+        }
+        let team = self.config.team;
+        {
+            let mut tasks = self.gc.referee.lock_tasks_mut();
+            for (i, action) in actions.chunks(ROBOT_ACTION_SIZE).enumerate() {
+                let task = &mut tasks[team.robot(i as u8 + 1) as usize];
+                // Like in the control socket, penalized robots are preempted
+                if task.preemption().is_some() {
+                    continue
+                }
+                task.control = (action[0], action[1], action[2]);
+                if action[3] > 0. {
+                    task.kick = Some(action[3].min(1.));
+                }
+            }
+        }
+        for _ in 0..self.config.frame_skip {
+            self.gc.step();
+        }
+        let gs = self.gc.get_game_state();
+        let reward: f64 = self.config.rewards.iter_mut()
+            .map(|r| r.reward(&self.gs, &gs, team))
+            .sum();
+        let goal = Team::all().into_iter()
+            .find(|t| gs.referee.teams.get(*t).score > self.gs.referee.teams.get(*t).score);
+        self.gs = gs;
+        self.steps += 1;
+        let terminated = self.config.terminate_on_goal && goal.is_some();
+        let truncated = !terminated && self.steps >= self.config.max_steps;
+        self.observe(obs);
+        Ok((reward, terminated, truncated, Info {
+            t: self.gc.simu.t,
+            goal
+        }))
+    }
+    /// Physical parameters of the current episode
+    pub fn physics_params(&self) -> &PhysicsParams {
+        &self.gc.simu.params
+    }
+    fn observe(&self, obs: &mut [f32]) {
+        let gs = &self.gs;
+        let team = self.config.team;
+        let robots = [team.robot(1), team.robot(2), team.opponent().robot(1), team.opponent().robot(2)];
+        let extended = gs.extended.as_ref().unwrap();
+        let ball = gs.ball.unwrap_or(DEFAULT_BALL_POS);
+        let mut values = Vec::with_capacity(obs.len());
+        for feature in self.config.observation.iter() {
+            match feature {
+                ObsFeature::Ball => values.extend([ball.x, ball.y]),
+                ObsFeature::BallVelocity => values.extend([extended.ball_velocity.x, extended.ball_velocity.y]),
+                ObsFeature::Robots => for r in robots {
+                    let pose = gs.markers.get(r);
+                    values.extend([pose.position.x, pose.position.y, pose.orientation.cos(), pose.orientation.sin()]);
+                },
+                ObsFeature::RobotsVelocities => for r in robots {
+                    let v = extended.velocities.get(r);
+                    values.extend([v.linear.x, v.linear.y, v.angular]);
+                },
+                ObsFeature::Penalties => for r in robots {
+                    values.push(if gs.referee.teams.robot(r).penalized {1.} else {0.});
+                }
+            }
+        }
+        for (o, v) in obs.iter_mut().zip(values) {
+            *o = v as f32;
+        }
+    }
+    /// Teleports the ball and the robots to random positions in the field, without overlap
+    fn randomize_positions(&mut self) {
+        let mut placed: Vec<Point2<f64>> = Vec::new();
+        for r in Robot::all() {
+            let pos = free_position(&mut self.rng, &mut placed, PARKING_SPOTS[r as usize]);
+            let orientation = self.rng.gen_range(-PI..PI);
+            self.gc.teleport_entity(self.gc.get_robot_handle(r), pos, Some(orientation));
+        }
+        let pos = free_position(&mut self.rng, &mut placed, DEFAULT_BALL_POS);
+        self.gc.teleport_entity(self.gc.get_ball_handle(), pos, None);
+    }
+}
+
+/// Random position in the field that is not too close to the ones in `placed`.
+/// Returns `fallback` if none is found after MAX_PLACEMENT_ATTEMPTS
+fn free_position(rng: &mut StdRng, placed: &mut Vec<Point2<f64>>, fallback: Point2<f64>) -> Point2<f64> {
+    let max = (FIELD.0/2. - ROBOT_RADIUS, FIELD.1/2. - ROBOT_RADIUS);
+    for _ in 0..MAX_PLACEMENT_ATTEMPTS {
+        let pos = Point2::new(rng.gen_range(-max.0..max.0), rng.gen_range(-max.1..max.1));
+        if placed.iter().all(|p| (p - pos).norm() > ROBOT_RADIUS*2. + 0.01) {
+            placed.push(pos);
+            return pos
+        }
+    }
+    warn!("No free position found, using {:?}", fallback);
+    placed.push(fallback);
+    fallback
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_action_size_is_rejected() {
+        let mut env = Env::new(EnvConfig::default());
+        env.reset(Some(0));
+        assert_eq!(env.step(&[0.; 3]).err(), Some(ActionSizeError { expected: ACTION_SIZE, actual: 3 }));
+        assert!(env.step(&[0.; ACTION_SIZE]).is_ok());
+
+        let mut envs = VecEnv::new(2, Some(1), |_| EnvConfig::default());
+        envs.reset(Some(0));
+        assert_eq!(envs.step(&[0.; ACTION_SIZE]), Err(ActionSizeError { expected: 2*ACTION_SIZE, actual: ACTION_SIZE }));
+        assert_eq!(envs.step(&[0.; 2*ACTION_SIZE]), Ok(()));
+    }
+}
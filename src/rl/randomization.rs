@@ -0,0 +1,49 @@
+//! Domain randomization : the physical parameters are sampled at each reset
+
+use std::ops::RangeInclusive;
+
+use rand::Rng;
+
+use crate::simulation::PhysicsParams;
+
+/// Ranges of the physical parameters (see `PhysicsParams` for the units). Use a range of one value to keep a parameter fixed
+#[derive(Clone, Debug)]
+pub struct Randomization {
+    pub ball_damping: RangeInclusive<f64>,
+    pub ball_restitution: RangeInclusive<f64>,
+    pub robot_mass: RangeInclusive<f64>,
+    pub robot_speed: RangeInclusive<f64>,
+    pub kicker_strength: RangeInclusive<f64>,
+    pub friction: RangeInclusive<f64>,
+    /// In frames
+    pub command_latency: RangeInclusive<usize>,
+}
+impl Default for Randomization {
+    /// Default parameters ±20%, and up to 20 ms of latency
+    fn default() -> Self {
+        let p = PhysicsParams::default();
+        let around = |v: f64| v*0.8..=v*1.2;
+        Self {
+            ball_damping: around(p.ball_damping),
+            ball_restitution: around(p.ball_restitution),
+            robot_mass: around(p.robot_mass),
+            robot_speed: around(p.robot_speed),
+            kicker_strength: around(p.kicker_strength),
+            friction: around(p.friction),
+            command_latency: 0..=20,
+        }
+    }
+}
+impl Randomization {
+    pub fn sample(&self, rng: &mut impl Rng) -> PhysicsParams {
+        PhysicsParams {
+            ball_damping: rng.gen_range(self.ball_damping.clone()),
+            ball_restitution: rng.gen_range(self.ball_restitution.clone()),
+            robot_mass: rng.gen_range(self.robot_mass.clone()),
+            robot_speed: rng.gen_range(self.robot_speed.clone()),
+            kicker_strength: rng.gen_range(self.kicker_strength.clone()),
+            friction: rng.gen_range(self.friction.clone()),
+            command_latency: rng.gen_range(self.command_latency.clone()),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Reward functions of the environment. They can be combined by adding several of them to the config
+
+use crate::game_state::{GameState, Team};
+use crate::constants::real::*;
+
+pub trait Reward: Send {
+    /// Reward of the agent's team for the transition from `prev` to `gs`
+    fn reward(&mut self, prev: &GameState, gs: &GameState, team: Team) -> f64;
+    /// Called at the start of each episode
+    fn reset(&mut self) {}
+}
+
+/// `scored` when the team scores and `-conceded` when the opponent scores
+pub struct Goals {
+    pub scored: f64,
+    pub conceded: f64,
+}
+impl Default for Goals {
+    fn default() -> Self {
+        Self {
+            scored: 1.,
+            conceded: 1.
+        }
+    }
+}
+impl Reward for Goals {
+    fn reward(&mut self, prev: &GameState, gs: &GameState, team: Team) -> f64 {
+        let scored = gs.referee.teams.get(team).score.saturating_sub(prev.referee.teams.get(team).score);
+        let conceded = gs.referee.teams.get(team.opponent()).score.saturating_sub(prev.referee.teams.get(team.opponent()).score);
+        self.scored * scored as f64 - self.conceded * conceded as f64
+    }
+}
+
+/// `weight` times the distance (in meters) the ball moved toward the opponent's goal
+pub struct BallProgress {
+    pub weight: f64,
+}
+impl Default for BallProgress {
+    fn default() -> Self {
+        Self {
+            weight: 1. / FIELD.0
+        }
+    }
+}
+impl Reward for BallProgress {
+    fn reward(&mut self, prev: &GameState, gs: &GameState, team: Team) -> f64 {
+        // After a goal, the ball is teleported to the center
+        let goals = |gs: &GameState| gs.referee.teams.blue.score + gs.referee.teams.green.score;
+        if goals(prev) != goals(gs) {
+            return 0.
+        }
+        match (prev.ball, gs.ball) {
+            (Some(prev), Some(ball)) => {
+                let goal = team.opponent().goal(&gs.referee);
+                self.weight * ((goal - prev).norm() - (goal - ball).norm())
+            },
+            _ => 0.
+        }
+    }
+}
+
+/// `-weight` each time a robot of the team gets penalized
+pub struct Penalties {
+    pub weight: f64,
+}
+impl Default for Penalties {
+    fn default() -> Self {
+        Self {
+            weight: 0.1
+        }
+    }
+}
+impl Reward for Penalties {
+    fn reward(&mut self, prev: &GameState, gs: &GameState, team: Team) -> f64 {
+        let prev = &prev.referee.teams.get(team).robots;
+        let robots = &gs.referee.teams.get(team).robots;
+        let new = [(&prev.one, &robots.one), (&prev.two, &robots.two)].into_iter()
+            .filter(|(prev, r)| r.penalized && !prev.penalized)
+            .count();
+        -self.weight * new as f64
+    }
+}
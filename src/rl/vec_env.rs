@@ -0,0 +1,182 @@
+//! Many environments stepped in parallel. Each thread owns a part of the environments
+
+use std::ops::Range;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use super::{ActionSizeError, Env, EnvConfig, Info, ACTION_SIZE};
+
+enum Job {
+    /// Seed of each environment
+    Reset(Vec<Option<u64>>),
+    /// Actions of each environment
+    Step(Vec<f32>),
+}
+
+/// Results of the environments of one worker
+struct Results {
+    observations: Vec<f32>,
+    rewards: Vec<f64>,
+    terminated: Vec<bool>,
+    truncated: Vec<bool>,
+    infos: Vec<Info>,
+    final_observations: Vec<Option<Vec<f32>>>,
+}
+impl Results {
+    fn new(n: usize, obs_size: usize) -> Self {
+        Self {
+            observations: vec![0.; n * obs_size],
+            rewards: vec![0.; n],
+            terminated: vec![false; n],
+            truncated: vec![false; n],
+            infos: vec![Info::default(); n],
+            final_observations: vec![None; n],
+        }
+    }
+}
+
+struct Worker {
+    jobs: Sender<Job>,
+    results: Receiver<Results>,
+    handle: JoinHandle<()>,
+    /// Indices of the environments of this worker
+    envs: Range<usize>,
+}
+
+fn run_worker(mut envs: Vec<Env>, obs_size: usize, jobs: Receiver<Job>, results: Sender<Results>) {
+    while let Ok(job) = jobs.recv() {
+        let mut res = Results::new(envs.len(), obs_size);
+        let observations = res.observations.chunks_mut(obs_size);
+        match job {
+            Job::Reset(seeds) => {
+                for ((env, seed), obs) in envs.iter_mut().zip(seeds).zip(observations) {
+                    env.reset_into(seed, obs);
+                }
+            },
+            Job::Step(actions) => {
+                for (i, ((env, actions), obs)) in envs.iter_mut().zip(actions.chunks(ACTION_SIZE)).zip(observations).enumerate() {
+                    // The size of the actions is checked by VecEnv::step
+                    let (reward, terminated, truncated, info) = env.step_into(actions, obs).unwrap();
+                    // Auto-reset, the last observation of the episode is kept in final_observations
+                    if terminated || truncated {
+                        res.final_observations[i] = Some(obs.to_vec());
+                        env.reset_into(None, obs);
+                    }
+                    res.rewards[i] = reward;
+                    res.terminated[i] = terminated;
+                    res.truncated[i] = truncated;
+                    res.infos[i] = info;
+                }
+            }
+        }
+        if results.send(res).is_err() {
+            break
+        }
+    }
+}
+
+pub struct VecEnv {
+    workers: Vec<Worker>,
+    obs_size: usize,
+    /// Observations of all the environments, one after the other (`num_envs() * observation_size()`)
+    pub observations: Vec<f32>,
+    pub rewards: Vec<f64>,
+    pub terminated: Vec<bool>,
+    pub truncated: Vec<bool>,
+    pub infos: Vec<Info>,
+    /// Last observation of the environments that have been reset during the last step
+    pub final_observations: Vec<Option<Vec<f32>>>,
+}
+impl VecEnv {
+    /// Creates `n` environments with `config(i)` spread on `threads` threads (all the cores if None)
+    pub fn new(n: usize, threads: Option<usize>, config: impl Fn(usize) -> EnvConfig) -> Self {
+        let threads = threads
+            .unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+            .clamp(1, n.max(1));
+        let envs: Vec<Env> = (0..n).map(|i| Env::new(config(i))).collect();
+        let obs_size = envs.first().map(|e| e.observation_size()).unwrap_or(0);
+        assert!(envs.iter().all(|e| e.observation_size() == obs_size), "All the environments must have the same observation size");
+
+        let mut envs = envs.into_iter();
+        let workers = (0..threads).map(|w| {
+            // The first workers take one more environment if n is not a multiple of threads
+            let start = w * (n / threads) + w.min(n % threads);
+            let len = n / threads + (w < n % threads) as usize;
+            let worker_envs: Vec<Env> = envs.by_ref().take(len).collect();
+            let (jobs, jobs_receiver) = channel();
+            let (results_sender, results) = channel();
+            let handle = thread::spawn(move || run_worker(worker_envs, obs_size, jobs_receiver, results_sender));
+            Worker {
+                jobs,
+                results,
+                handle,
+                envs: start..start + len
+            }
+        }).collect();
+
+        let res = Results::new(n, obs_size);
+        Self {
+            workers,
+            obs_size,
+            observations: res.observations,
+            rewards: res.rewards,
+            terminated: res.terminated,
+            truncated: res.truncated,
+            infos: res.infos,
+            final_observations: res.final_observations
+        }
+    }
+    pub fn num_envs(&self) -> usize {
+        self.rewards.len()
+    }
+    pub fn observation_size(&self) -> usize {
+        self.obs_size
+    }
+    /// Resets all the environments. Environment `i` uses the seed `seed + i`. Returns the observations
+    pub fn reset(&mut self, seed: Option<u64>) -> &[f32] {
+        for w in self.workers.iter() {
+            let seeds = w.envs.clone().map(|i| seed.map(|s| s + i as u64)).collect();
+            w.jobs.send(Job::Reset(seeds)).unwrap();
+        }
+        self.collect();
+        &self.observations
+    }
+    /// `actions` contains the actions of all the environments, one after the other (`num_envs() * ACTION_SIZE`).
+    /// The results are in `observations`, `rewards`, `terminated`, `truncated`, `infos` and `final_observations`.
+    /// Finished environments are reset automatically. Returns an error if there are not `num_envs() * ACTION_SIZE` actions
+    pub fn step(&mut self, actions: &[f32]) -> Result<(), ActionSizeError> {
+        if actions.len() != self.num_envs() * ACTION_SIZE {
+            return Err(ActionSizeError { expected: self.num_envs() * ACTION_SIZE, actual: actions.len() })
+        }
+        for w in self.workers.iter() {
+            let actions = actions[w.envs.start * ACTION_SIZE..w.envs.end * ACTION_SIZE].to_vec();
+            w.jobs.send(Job::Step(actions)).unwrap();
+        }
+        self.collect();
+        Ok(())
+    }
+    /// Copies the results of the workers
+    fn collect(&mut self) {
+        for w in self.workers.iter() {
+            let res = w.results.recv().expect("Environment thread panicked");
+            let r = w.envs.clone();
+            self.observations[r.start * self.obs_size..r.end * self.obs_size].copy_from_slice(&res.observations);
+            self.rewards[r.clone()].copy_from_slice(&res.rewards);
+            self.terminated[r.clone()].copy_from_slice(&res.terminated);
+            self.truncated[r.clone()].copy_from_slice(&res.truncated);
+            for (i, (info, final_obs)) in r.zip(res.infos.into_iter().zip(res.final_observations)) {
+                self.infos[i] = info;
+                self.final_observations[i] = final_obs;
+            }
+        }
+    }
+}
+impl Drop for VecEnv {
+    fn drop(&mut self) {
+        for w in self.workers.drain(..) {
+            // Closing the channel stops the worker
+            drop(w.jobs);
+            let _ = w.handle.join();
+        }
+    }
+}
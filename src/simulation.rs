@@ -12,6 +12,38 @@ const ROBOT_COLLISION_GROUPS: [Group; 4] = [
 ];
 const KICKER_COLLISION_GROUP: Group = Group::GROUP_6;
 
+/// Physical parameters that can be changed without rebuilding the simulation. Distances in real units (meters)
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PhysicsParams {
+    pub ball_damping: f64,
+    pub ball_restitution: f64,
+    /// In the simulation's unit, like ROBOT_MASS
+    pub robot_mass: f64,
+    /// In m/s
+    pub robot_speed: f64,
+    pub kicker_strength: f64,
+    /// Friction coefficient of the ball and the robots
+    pub friction: f64,
+    /// Delay before the commands are applied, in frames
+    pub command_latency: usize,
+}
+impl Default for PhysicsParams {
+    fn default() -> Self {
+        use crate::constants::real;
+        Self {
+            ball_damping: real::BALL_DAMPING,
+            ball_restitution: real::BALL_RESTITUTION,
+            robot_mass: ROBOT_MASS,
+            robot_speed: real::ROBOT_SPEED,
+            kicker_strength: real::KICKER_STRENGTH,
+            friction: ColliderBuilder::default_friction(),
+            command_latency: 0
+        }
+    }
+}
+
 pub struct Simulation {
     pub bodies: RigidBodySet,
     pub colliders: ColliderSet,
@@ -19,6 +51,7 @@ pub struct Simulation {
     pub ball: RigidBodyHandle,
     pub ball_col: ColliderHandle,
     pub robots: [RigidBodyHandle; 4],
+    pub robot_cols: [ColliderHandle; 4],
     pub kickers: [RigidBodyHandle; 4],
     pub kicker_joints: [ImpulseJointHandle; 4],
     pub kicker_timer: [usize; 4],
@@ -34,6 +67,7 @@ pub struct Simulation {
     query_pipeline: QueryPipeline,
     physics_hooks: (),
     events: (),
+    pub params: PhysicsParams,
     // Actual frame
     pub t: usize,
 }
@@ -76,7 +110,7 @@ impl Simulation {
                 .angular_damping(ROBOT_ANGULAR_DAMPING)
                 .can_sleep(false)
         ));
-        for (robot, collision_group) in robots.iter().zip(ROBOT_COLLISION_GROUPS.iter()) {
+        let robot_cols = std::array::from_fn(|i| {
             const r: f64 = ROBOT_RADIUS - 0.001;
             colliders.insert_with_parent(
                 // Collider is a regular hexagon with radius ROBOT_RADIUS
@@ -88,14 +122,14 @@ impl Simulation {
                     point![-r * 0.866, -r * 0.5],
                     point![-r * 0.866, r * 0.5],
                 ], 0.001).unwrap()
-                    .mass(10.)
+                    .mass(ROBOT_MASS)
                     .restitution(ROBOT_RESTITUTION)
                     .restitution_combine_rule(CoefficientCombineRule::Min)
-                    .collision_groups(InteractionGroups::new(*collision_group, Group::all())),
-                *robot,
+                    .collision_groups(InteractionGroups::new(ROBOT_COLLISION_GROUPS[i], Group::all())),
+                robots[i],
                 &mut bodies,
-            );
-        }
+            )
+        });
 
         // Create kickers
         let kickers = std::array::from_fn(|i| bodies.insert(
@@ -141,6 +175,7 @@ impl Simulation {
             ball,
             ball_col,
             robots,
+            robot_cols,
             kickers,
             kicker_joints,
             kicker_timer: [0; 4],
@@ -159,9 +194,23 @@ impl Simulation {
             query_pipeline: QueryPipeline::new(),
             physics_hooks: (),
             events: (),
+            params: PhysicsParams::default(),
             t: 0,
         }
     }
+    /// Applies new physical parameters to the existing bodies
+    pub fn set_params(&mut self, params: PhysicsParams) {
+        self.bodies[self.ball].set_linear_damping(params.ball_damping);
+        let ball_col = &mut self.colliders[self.ball_col];
+        ball_col.set_restitution(params.ball_restitution);
+        ball_col.set_friction(params.friction);
+        for col in self.robot_cols {
+            let col = &mut self.colliders[col];
+            col.set_mass(params.robot_mass);
+            col.set_friction(params.friction);
+        }
+        self.params = params;
+    }
     pub fn step(&mut self) {
         self.physics_pipeline.step(
             &self.gravity,
@@ -191,7 +240,7 @@ impl Simulation {
                     .data
                     .as_prismatic_mut()
                     .unwrap()
-                    .set_motor_position(0., KICKER_STRENGTH, 0.);
+                    .set_motor_position(0., self.params.kicker_strength*MULTIPLIER, 0.);
             } else {
                 *t -= 1;
             }
@@ -237,7 +286,7 @@ impl Simulation {
             .data
             .as_prismatic_mut()
             .unwrap()
-            .set_motor_position(10., KICKER_STRENGTH*f, 0.);
+            .set_motor_position(10., self.params.kicker_strength*MULTIPLIER*f, 0.);
         self.kicker_timer[id as usize] = 10;
     }
     pub fn reset(&mut self) {
@@ -0,0 +1,38 @@
+use crate::game_state::{GameState, Pose, Team};
+use crate::constants::real::*;
+use super::{can_kick, Command, Strategy};
+
+/// Goes behind the ball, pushes it toward the opponent's goal and kicks when aligned
+pub struct Chaser {
+    /// Number of the controlled robot (1 or 2)
+    pub number: u8,
+}
+impl Strategy for Chaser {
+    fn update(&mut self, gs: &GameState, team: Team) -> Vec<Command> {
+        let robot = team.robot(self.number);
+        let pose = gs.markers.get(robot);
+        let ball = match gs.ball {
+            Some(ball) => ball,
+            None => return Vec::new()
+        };
+        let dir = (team.opponent().goal(&gs.referee) - ball).normalize();
+        let orientation = dir.y.atan2(dir.x);
+        let to_ball = ball - pose.position;
+        // The robot is behind the ball if it's on the line between the ball and its own side
+        let behind = to_ball.norm() < ROBOT_RADIUS*3. && to_ball.normalize().dot(&dir) > 0.9;
+        let target = Pose {
+            position: if behind {
+                ball
+            } else {
+                ball - dir*(ROBOT_RADIUS + BALL_RADIUS + 0.02)
+            },
+            orientation
+        };
+        let (_, control) = pose.goto(&target);
+        let mut cmds = vec![Command::Control(robot, control)];
+        if behind && can_kick(pose, ball) {
+            cmds.push(Command::Kick(robot, 1.));
+        }
+        cmds
+    }
+}
@@ -0,0 +1,39 @@
+use std::f64::consts::PI;
+
+use nalgebra::Point2;
+
+use crate::game_state::{GameState, Pose, Team};
+use crate::constants::real::*;
+use super::{can_kick, Command, Strategy};
+
+/// Stays in front of its goal, follows the ball on the y axis and kicks it away when it comes close
+pub struct Goalkeeper {
+    /// Number of the controlled robot (1 or 2)
+    pub number: u8,
+}
+impl Strategy for Goalkeeper {
+    fn update(&mut self, gs: &GameState, team: Team) -> Vec<Command> {
+        let robot = team.robot(self.number);
+        let pose = gs.markers.get(robot);
+        let ball = match gs.ball {
+            Some(ball) => ball,
+            None => return Vec::new()
+        };
+        let goal = team.goal(&gs.referee);
+        let side = goal.x.signum();
+        let target = Pose {
+            position: Point2::new(
+                goal.x - side*(ROBOT_RADIUS*1.5),
+                ball.y.clamp(-GOAL_HEIGHT/2., GOAL_HEIGHT/2.)
+            ),
+            // Look at the opponent's goal
+            orientation: if side < 0. {0.} else {PI}
+        };
+        let (_, control) = pose.goto(&target);
+        let mut cmds = vec![Command::Control(robot, control)];
+        if can_kick(pose, ball) {
+            cmds.push(Command::Kick(robot, 1.));
+        }
+        cmds
+    }
+}
@@ -0,0 +1,61 @@
+//! Strategies written in Rust that run inside the game controller, without sockets
+
+use nalgebra::Point2;
+
+use crate::game_state::{GameState, Pose, Robot, Team};
+use crate::constants::real::*;
+
+mod chaser;
+mod goalkeeper;
+
+pub use chaser::Chaser;
+pub use goalkeeper::Goalkeeper;
+
+/// Same commands as the ones of the control socket
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Command {
+    /// (x, y, rotation) in the robot's frame
+    Control(Robot, (f32, f32, f32)),
+    /// strength between 0. and 1.
+    Kick(Robot, f32),
+}
+
+pub trait Strategy: Send {
+    /// Called by the game controller at the decision rate. Commands for robots of the other team are ignored
+    fn update(&mut self, gs: &GameState, team: Team) -> Vec<Command>;
+}
+
+/// Runs several strategies, for example one per robot
+pub struct Multi(pub Vec<Box<dyn Strategy>>);
+impl Strategy for Multi {
+    fn update(&mut self, gs: &GameState, team: Team) -> Vec<Command> {
+        self.0.iter_mut().flat_map(|s| s.update(gs, team)).collect()
+    }
+}
+
+/// Built-in strategy from its name : "goalkeeper", "chaser" or "baseline" (goalkeeper + chaser)
+pub fn by_name(name: &str) -> Option<Box<dyn Strategy>> {
+    match name {
+        "goalkeeper" => Some(Box::new(Goalkeeper { number: 1 })),
+        "chaser" => Some(Box::new(Multi(vec![
+            Box::new(Chaser { number: 1 }),
+            Box::new(Chaser { number: 2 }),
+        ]))),
+        "baseline" => Some(Box::new(Multi(vec![
+            Box::new(Goalkeeper { number: 1 }),
+            Box::new(Chaser { number: 2 }),
+        ]))),
+        _ => None
+    }
+}
+
+/// True if the ball is close enough to the kicker to be kicked
+fn can_kick(pose: &Pose, ball: Point2<f64>) -> bool {
+    let (sin, cos) = pose.orientation.sin_cos();
+    let d = ball - pose.position;
+    // Ball in the robot's frame
+    let x = cos*d.x + sin*d.y;
+    let y = -sin*d.x + cos*d.y;
+    x > 0. && x < ROBOT_RADIUS + BALL_RADIUS + KICKER_REACH && y.abs() < ROBOT_RADIUS/2.
+}